@@ -0,0 +1,84 @@
+// times views that lie entirely inside the mandelbrot set, where every point used to run all of its iterations.
+// both runs go through render_grid on the same worker pool, so the difference is only the interior shortcuts.
+// run with: cargo run --release --example interior_benchmark
+extern crate num;
+use num::complex::Complex;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use term_fractals::error::Result;
+use term_fractals::fractal::{DefaultView, Escape, Fractal};
+use term_fractals::mandelbrot::{Mandelbrot, BAILOUT_RADIUS, QUADRATIC};
+use term_fractals::render::{render_grid, Grid};
+use term_fractals::viewport::Viewport;
+
+const SIZE: (u16, u16) = (160, 96);
+const ITERATIONS: u16 = 5000;
+
+// the quadratic mandelbrot set without the cardioid, bulb and cycle checks
+struct Naive;
+
+impl Fractal for Naive {
+    fn name(&self) -> String {
+        String::from("Mandelbrot without shortcuts")
+    }
+    fn default_view(&self) -> DefaultView {
+        Mandelbrot { d: QUADRATIC }.default_view()
+    }
+    fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape {
+        let mut z = Complex::new(0.0, 0.0);
+        for i in 0..n_iter {
+            z = z * z + c;
+            if z.norm_sqr() > BAILOUT_RADIUS * BAILOUT_RADIUS {
+                return Escape::escaped(i, z.norm_sqr(), 2.0);
+            }
+        }
+        Escape::bounded(n_iter, z.norm_sqr())
+    }
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn count<K: Ord>(grid: &Grid, key: impl Fn(&Escape) -> K) -> Result<BTreeMap<K, usize>> {
+    let mut counts = BTreeMap::new();
+    for y in 0..grid.size.1 {
+        for x in 0..grid.size.0 {
+            *counts.entry(key(&grid.get(x, y)?)).or_insert(0) += 1;
+        }
+    }
+    Ok(counts)
+}
+
+fn main() -> Result<()> {
+    let naive: Arc<dyn Fractal> = Arc::new(Naive);
+    let fractal: Arc<dyn Fractal> = Arc::new(Mandelbrot { d: QUADRATIC });
+    let views = [
+        ("main cardioid", Complex::new(-0.2, 0.0), 0.15),
+        ("period 2 bulb", Complex::new(-1.0, 0.0), 0.1),
+        ("period 3 bulb", Complex::new(-0.122, 0.745), 0.03),
+        ("period 4 bulb", Complex::new(-1.31, 0.0), 0.02),
+    ];
+    println!("{} points, {} iterations each", SIZE.0 as u32 * SIZE.1 as u32, ITERATIONS);
+    for (name, center, radius) in views {
+        let viewport = Viewport::new(center, 2.0 * radius / SIZE.0 as f64, 1.0);
+        let (naive_grid, naive_time) = time(|| render_grid(&naive, &viewport, SIZE, ITERATIONS));
+        let (grid, time) = time(|| render_grid(&fractal, &viewport, SIZE, ITERATIONS));
+        let interior = count(&naive_grid?, |escape| escape.interior)?;
+        let periods = count(&grid?, |escape| escape.period)?;
+        println!(
+            "{:<14} without shortcuts {:>9.1?} ({} interior)  with shortcuts {:>9.1?}  {:>6.1}x  periods {:?}",
+            name,
+            naive_time,
+            interior.get(&true).unwrap_or(&0),
+            time,
+            naive_time.as_secs_f64() / time.as_secs_f64(),
+            periods
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+// times the worker pool against the scheme it replaced, which spawned 10 new threads for every frame,
+// gave each an equal run of rows and sent every pixel back as its own message.
+// run with: cargo run --release --example pool_benchmark
+extern crate num;
+use num::complex::Complex;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use term_fractals::error::{Error, Result};
+use term_fractals::fractal::{Escape, Fractal};
+use term_fractals::mandelbrot::{Mandelbrot, QUADRATIC};
+use term_fractals::pool;
+use term_fractals::render::render_grid;
+use term_fractals::viewport::Viewport;
+
+const SIZE: (u16, u16) = (160, 96);
+const FRAMES: u32 = 10;
+
+// the old compute_points: fixed chunks on fresh threads, one message per pixel
+fn render_before(fractal: &Arc<dyn Fractal>, viewport: &Viewport, size: (u16, u16), n_iter: u16) -> Result<Vec<Escape>> {
+    let n_threads = 10;
+    let points: Vec<(u16, u16)> = (0..size.1).flat_map(|y| (0..size.0).map(move |x| (x, y))).collect();
+    let chunk_size = std::cmp::max(1, points.len() / n_threads);
+    let (tx, rx) = mpsc::channel::<(usize, Escape)>();
+    for (chunk_index, chunk) in points.chunks(chunk_size).enumerate() {
+        let local_tx = tx.clone();
+        let fractal = Arc::clone(fractal);
+        let viewport = *viewport;
+        let chunk = chunk.to_vec();
+        thread::spawn(move || {
+            for (offset, (x, y)) in chunk.into_iter().enumerate() {
+                let escape = fractal.compute(viewport.point(x as f64, y as f64, size), n_iter);
+                if local_tx.send((chunk_index * chunk_size + offset, escape)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx);
+    let mut values = vec![None; points.len()];
+    for _ in 0..points.len() {
+        let (index, escape) = rx.recv().map_err(|_| Error::Worker("a render thread stopped early"))?;
+        values[index] = Some(escape);
+    }
+    Ok(values.into_iter().flatten().collect())
+}
+
+// the average time of a frame
+fn time(mut frame: impl FnMut() -> Result<()>) -> Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame()?;
+    }
+    Ok(start.elapsed() / FRAMES)
+}
+
+fn main() -> Result<()> {
+    let fractal: Arc<dyn Fractal> = Arc::new(Mandelbrot { d: QUADRATIC });
+    // the views mix cheap exterior with expensive boundary, which the equal chunks split unevenly
+    let views = [
+        ("whole set", Complex::new(-0.6, 0.0), 1.6, 200),
+        ("seahorse valley", Complex::new(-0.745, 0.11), 0.01, 1000),
+        ("elephant valley", Complex::new(0.28, 0.008), 0.01, 1000),
+        ("outside the set", Complex::new(1.5, 1.5), 0.5, 200),
+    ];
+    println!(
+        "{} points, {} frames each, pool of {} threads (set {} to change it)",
+        SIZE.0 as u32 * SIZE.1 as u32,
+        FRAMES,
+        pool::global().threads(),
+        pool::THREADS_VARIABLE
+    );
+    for (name, center, radius, n_iter) in views {
+        let viewport = Viewport::new(center, 2.0 * radius / SIZE.0 as f64, 1.0);
+        let before = time(|| render_before(&fractal, &viewport, SIZE, n_iter).map(|_| ()))?;
+        let after = time(|| render_grid(&fractal, &viewport, SIZE, n_iter).map(|_| ()))?;
+        println!(
+            "{:<16} {:>5} iterations  before {:>9.1?}  after {:>9.1?}  {:>5.2}x",
+            name,
+            n_iter,
+            before,
+            after,
+            before.as_secs_f64() / after.as_secs_f64()
+        );
+    }
+    Ok(())
+}
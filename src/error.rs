@@ -0,0 +1,62 @@
+use std::fmt;
+use std::io;
+
+// everything that can go wrong while exploring a fractal
+#[derive(Debug)]
+pub enum Error {
+    // talking to the terminal failed, operation says what was being done
+    Io {
+        operation: &'static str,
+        source: io::Error,
+    },
+    // a buffer or screen coordinate outside of the given size
+    OutOfBounds { x: u16, y: u16, size: (u16, u16) },
+    // a pixel that should have been computed still holds Pixel::Recompute
+    UnrenderedPixel { x: u16, y: u16 },
+    // some other assumption about the state of the screen did not hold
+    RenderState(&'static str),
+    // a render thread died or stopped sending results
+    Worker(&'static str),
+    // a newer render took over before this one finished
+    Cancelled,
+    // text typed by the user could not be understood, column counts characters from 1
+    Parse { column: usize, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // a closure for map_err that wraps an io::Error with what was being done
+    pub fn io(operation: &'static str) -> impl FnOnce(io::Error) -> Error {
+        move |source| Error::Io { operation, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { operation, .. } => write!(f, "could not {}", operation),
+            Error::OutOfBounds { x, y, size } => write!(
+                f,
+                "coordinates ({}, {}) are outside of the {}x{} buffer",
+                x, y, size.0, size.1
+            ),
+            Error::UnrenderedPixel { x, y } => {
+                write!(f, "the pixel at ({}, {}) was never computed", x, y)
+            }
+            Error::RenderState(reason) => write!(f, "invalid render state: {}", reason),
+            Error::Worker(reason) => write!(f, "render worker failed: {}", reason),
+            Error::Cancelled => write!(f, "the render was cancelled by a newer one"),
+            Error::Parse { column, message } => write!(f, "column {}: {}", column, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
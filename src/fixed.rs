@@ -0,0 +1,402 @@
+use num::complex::Complex;
+use std::cmp::Ordering;
+
+// enough fraction bits for any scale an f64 can hold, which is what the viewport zooms with
+pub const MAX_FRACTION_LIMBS: usize = 34;
+// orbits are only followed while |z| stays below the bailout radius, so two limbs of integer part are plenty
+const INTEGER_LIMBS: usize = 2;
+const LIMBS: usize = MAX_FRACTION_LIMBS + INTEGER_LIMBS;
+
+// a signed fixed point number with a runtime number of 32 bit fraction limbs. it lives in a fixed size
+// array so that it stays Copy and never allocates, only the first fraction_limbs + INTEGER_LIMBS are used.
+// two numbers have to have the same number of fraction limbs to be combined
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fixed {
+    negative: bool,
+    fraction_limbs: usize,
+    limbs: [u32; LIMBS], // magnitude, least significant limb first
+}
+
+impl Fixed {
+    pub fn zero(fraction_limbs: usize) -> Fixed {
+        Fixed {
+            negative: false,
+            fraction_limbs: fraction_limbs.min(MAX_FRACTION_LIMBS),
+            limbs: [0; LIMBS],
+        }
+    }
+    // exact, except for the bits of x below the last fraction limb
+    pub fn from_f64(x: f64, fraction_limbs: usize) -> Fixed {
+        let mut fixed = Fixed::zero(fraction_limbs);
+        if x == 0.0 || !x.is_finite() {
+            return fixed;
+        }
+        fixed.negative = x < 0.0;
+        let bits = x.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        // subnormals have no implicit leading bit, and the same scale as the smallest normal exponent
+        let mantissa = if exponent == 0 {
+            bits & ((1 << 52) - 1)
+        } else {
+            (bits & ((1 << 52) - 1)) | (1 << 52)
+        };
+        // x = mantissa * 2^(exponent - 1075), and bit 0 of the fixed point number is worth 2^(-32 * fraction_limbs)
+        let shift = exponent.max(1) - 1075 + 32 * fixed.fraction_limbs as i64;
+        for bit in 0..53 {
+            let position = shift + bit;
+            if mantissa & (1 << bit) != 0 && position >= 0 && (position as usize) < 32 * fixed.used() {
+                fixed.limbs[position as usize / 32] |= 1 << (position % 32);
+            }
+        }
+        fixed
+    }
+    // the fraction by horner's method from the lowest limb up. scaling every limb by its own weight instead
+    // would need powers of two below what an f64 can hold, and flush the smallest values to 0
+    pub fn to_f64(&self) -> f64 {
+        let mut value = 0.0;
+        for limb in &self.limbs[..self.fraction_limbs] {
+            value = (value + *limb as f64) * 2f64.powi(-32);
+        }
+        for (index, limb) in self.limbs[self.fraction_limbs..self.used()].iter().enumerate() {
+            value += *limb as f64 * 2f64.powi(32 * index as i32);
+        }
+        if self.negative {
+            -value
+        } else {
+            value
+        }
+    }
+    pub fn fraction_limbs(&self) -> usize {
+        self.fraction_limbs
+    }
+    // more fraction limbs keep the value exactly, fewer cut off the lowest bits
+    pub fn with_fraction_limbs(&self, fraction_limbs: usize) -> Fixed {
+        let mut fixed = Fixed::zero(fraction_limbs);
+        fixed.negative = self.negative;
+        for index in 0..self.used() {
+            let target = index as i64 + fixed.fraction_limbs as i64 - self.fraction_limbs as i64;
+            if target >= 0 && (target as usize) < fixed.used() {
+                fixed.limbs[target as usize] = self.limbs[index];
+            }
+        }
+        fixed.normalize_zero();
+        fixed
+    }
+    fn used(&self) -> usize {
+        self.fraction_limbs + INTEGER_LIMBS
+    }
+    fn is_zero(&self) -> bool {
+        self.limbs[..self.used()].iter().all(|limb| *limb == 0)
+    }
+    // zero is never negative, so that equal values compare equal
+    fn normalize_zero(&mut self) {
+        if self.is_zero() {
+            self.negative = false;
+        }
+    }
+    fn compare_magnitude(&self, other: &Fixed) -> Ordering {
+        for index in (0..self.used()).rev() {
+            match self.limbs[index].cmp(&other.limbs[index]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+    // |a| + |b|, overflow past the integer limbs is dropped
+    fn add_magnitudes(a: &Fixed, b: &Fixed, negative: bool) -> Fixed {
+        let mut sum = Fixed::zero(a.fraction_limbs);
+        sum.negative = negative;
+        let mut carry = 0u64;
+        for index in 0..a.used() {
+            let total = a.limbs[index] as u64 + b.limbs[index] as u64 + carry;
+            sum.limbs[index] = total as u32;
+            carry = total >> 32;
+        }
+        sum
+    }
+    // |a| - |b| for |a| >= |b|
+    fn subtract_magnitudes(a: &Fixed, b: &Fixed, negative: bool) -> Fixed {
+        let mut difference = Fixed::zero(a.fraction_limbs);
+        difference.negative = negative;
+        let mut borrow = 0i64;
+        for index in 0..a.used() {
+            let mut total = a.limbs[index] as i64 - b.limbs[index] as i64 - borrow;
+            borrow = 0;
+            if total < 0 {
+                total += 1 << 32;
+                borrow = 1;
+            }
+            difference.limbs[index] = total as u32;
+        }
+        difference.normalize_zero();
+        difference
+    }
+    pub fn add(&self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.fraction_limbs, other.fraction_limbs);
+        if self.negative == other.negative {
+            return Fixed::add_magnitudes(self, other, self.negative);
+        }
+        match self.compare_magnitude(other) {
+            Ordering::Less => Fixed::subtract_magnitudes(other, self, other.negative),
+            _ => Fixed::subtract_magnitudes(self, other, self.negative),
+        }
+    }
+    pub fn negate(&self) -> Fixed {
+        let mut negated = *self;
+        negated.negative = !self.negative;
+        negated.normalize_zero();
+        negated
+    }
+    pub fn sub(&self, other: &Fixed) -> Fixed {
+        self.add(&other.negate())
+    }
+    // truncates the product to the same number of fraction limbs
+    pub fn mul(&self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.fraction_limbs, other.fraction_limbs);
+        let used = self.used();
+        let mut wide = [0u64; 2 * LIMBS];
+        for i in 0..used {
+            if self.limbs[i] == 0 {
+                continue;
+            }
+            let mut carry = 0u64;
+            for j in 0..used {
+                let total = self.limbs[i] as u64 * other.limbs[j] as u64 + wide[i + j] + carry;
+                wide[i + j] = total & 0xffff_ffff;
+                carry = total >> 32;
+            }
+            wide[i + used] += carry;
+        }
+        let mut product = Fixed::zero(self.fraction_limbs);
+        product.negative = self.negative != other.negative;
+        for index in 0..used {
+            product.limbs[index] = wide[index + self.fraction_limbs] as u32;
+        }
+        product.normalize_zero();
+        product
+    }
+    pub fn double(&self) -> Fixed {
+        Fixed::add_magnitudes(self, self, self.negative)
+    }
+    // the number in decimal with the given number of digits after the point, truncated
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let mut integer = 0u64;
+        for index in (0..INTEGER_LIMBS).rev() {
+            integer = (integer << 32) | self.limbs[self.fraction_limbs + index] as u64;
+        }
+        let mut text = format!("{}{}", if self.negative { "-" } else { "" }, integer);
+        if digits > 0 {
+            text.push('.');
+        }
+        let mut fraction = self.limbs;
+        for _ in 0..digits {
+            // multiplying the fraction by 10 pushes the next digit into the integer limbs
+            let mut carry = 0u64;
+            for limb in fraction[..self.fraction_limbs].iter_mut() {
+                let total = *limb as u64 * 10 + carry;
+                *limb = total as u32;
+                carry = total >> 32;
+            }
+            text.push(char::from(b'0' + carry as u8));
+        }
+        text
+    }
+}
+
+// a point of the complex plane in fixed point
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FixedComplex {
+    pub re: Fixed,
+    pub im: Fixed,
+}
+
+impl FixedComplex {
+    pub fn from_f64(z: Complex<f64>, fraction_limbs: usize) -> FixedComplex {
+        FixedComplex {
+            re: Fixed::from_f64(z.re, fraction_limbs),
+            im: Fixed::from_f64(z.im, fraction_limbs),
+        }
+    }
+    pub fn to_f64(&self) -> Complex<f64> {
+        Complex::new(self.re.to_f64(), self.im.to_f64())
+    }
+    pub fn fraction_limbs(&self) -> usize {
+        self.re.fraction_limbs()
+    }
+    pub fn with_fraction_limbs(&self, fraction_limbs: usize) -> FixedComplex {
+        FixedComplex {
+            re: self.re.with_fraction_limbs(fraction_limbs),
+            im: self.im.with_fraction_limbs(fraction_limbs),
+        }
+    }
+    pub fn add(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex {
+            re: self.re.add(&other.re),
+            im: self.im.add(&other.im),
+        }
+    }
+    pub fn sub(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex {
+            re: self.re.sub(&other.re),
+            im: self.im.sub(&other.im),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(x: f64) -> Fixed {
+        Fixed::from_f64(x, 4)
+    }
+
+    #[test]
+    fn f64_round_trips() {
+        for x in [0.0, 1.0, -1.0, 0.5, -2.75, 3.0e-20, -1.2345678901234567, 1.0e9] {
+            assert_eq!(fixed(x).to_f64(), x);
+        }
+    }
+
+    #[test]
+    fn subnormals_are_exact() {
+        // the smallest subnormal is 2^-1074, bit 14 of the lowest of 34 fraction limbs
+        let smallest = Fixed::from_f64(f64::from_bits(1), MAX_FRACTION_LIMBS);
+        let mut expected = Fixed::zero(MAX_FRACTION_LIMBS);
+        expected.limbs[0] = 1 << 14;
+        assert_eq!(smallest, expected);
+        assert_eq!(Fixed::from_f64(-f64::from_bits(1), MAX_FRACTION_LIMBS), expected.negate());
+        // the largest subnormal sits just below the smallest normal
+        let largest = Fixed::from_f64(f64::from_bits((1 << 52) - 1), MAX_FRACTION_LIMBS);
+        let normal = Fixed::from_f64(f64::MIN_POSITIVE, MAX_FRACTION_LIMBS);
+        assert_eq!(largest.add(&smallest), normal);
+        for x in [f64::from_bits(1), f64::MIN_POSITIVE / 2.0, f64::MIN_POSITIVE / 1024.0] {
+            assert_eq!(Fixed::from_f64(x, MAX_FRACTION_LIMBS).to_f64(), x);
+        }
+    }
+
+    #[test]
+    fn bits_below_the_last_limb_are_dropped() {
+        assert!(Fixed::from_f64(1.0e-40, 1).is_zero());
+        assert_eq!(Fixed::from_f64(1.0 + 2f64.powi(-40), 1).to_f64(), 1.0);
+        assert_eq!(Fixed::from_f64(f64::NAN, 2), Fixed::zero(2));
+    }
+
+    #[test]
+    fn signs_of_sums_and_differences() {
+        assert_eq!(fixed(2.5).add(&fixed(-4.0)).to_f64(), -1.5);
+        assert_eq!(fixed(-2.5).add(&fixed(4.0)).to_f64(), 1.5);
+        assert_eq!(fixed(-2.5).add(&fixed(-4.0)).to_f64(), -6.5);
+        assert_eq!(fixed(2.5).sub(&fixed(4.0)).to_f64(), -1.5);
+        assert_eq!(fixed(-2.5).sub(&fixed(-4.0)).to_f64(), 1.5);
+        assert_eq!(fixed(3.0).negate().to_f64(), -3.0);
+    }
+
+    #[test]
+    fn signs_of_products() {
+        assert_eq!(fixed(1.5).mul(&fixed(-2.0)).to_f64(), -3.0);
+        assert_eq!(fixed(-1.5).mul(&fixed(-2.0)).to_f64(), 3.0);
+        assert_eq!(fixed(-0.25).mul(&fixed(0.5)).to_f64(), -0.125);
+        assert_eq!(fixed(-1.25).double().to_f64(), -2.5);
+    }
+
+    #[test]
+    fn zero_is_never_negative() {
+        assert_eq!(fixed(1.5).sub(&fixed(1.5)), Fixed::zero(4));
+        assert_eq!(fixed(-1.5).add(&fixed(1.5)), Fixed::zero(4));
+        assert_eq!(Fixed::zero(4).negate(), Fixed::zero(4));
+        assert_eq!(fixed(-1.0).mul(&Fixed::zero(4)), Fixed::zero(4));
+        // a product too small for the limbs is truncated to zero, with no sign left over
+        let tiny = Fixed::from_f64(-2f64.powi(-40), 2);
+        assert_eq!(tiny.mul(&tiny.negate()), Fixed::zero(2));
+    }
+
+    #[test]
+    fn carries_cross_limbs() {
+        // the lowest bit of the number, one limb below the next
+        let ulp = Fixed::from_f64(2f64.powi(-64), 2);
+        let below_one = Fixed::from_f64(1.0, 2).sub(&ulp);
+        assert_eq!(below_one.limbs[..4], [u32::MAX, u32::MAX, 0, 0]);
+        let one = below_one.add(&ulp);
+        assert_eq!(one.limbs[..4], [0, 0, 1, 0]);
+        assert_eq!(one, Fixed::from_f64(1.0, 2));
+        // into the second integer limb
+        let big = Fixed::from_f64(4294967295.0, 2).add(&Fixed::from_f64(1.0, 2));
+        assert_eq!(big.limbs[..4], [0, 0, 0, 1]);
+        assert_eq!(big.to_f64(), 4294967296.0);
+    }
+
+    #[test]
+    fn borrows_cross_limbs() {
+        let ulp = Fixed::from_f64(2f64.powi(-64), 2);
+        let difference = Fixed::from_f64(1.0, 2).sub(&ulp);
+        assert!(!difference.negative);
+        let back = ulp.sub(&Fixed::from_f64(1.0, 2));
+        assert!(back.negative);
+        assert_eq!(back.negate(), difference);
+    }
+
+    #[test]
+    fn products_keep_all_fraction_limbs() {
+        // (1 + 2^-40)^2 = 1 + 2^-39 + 2^-80, the last term needs the third limb
+        let x = Fixed::from_f64(1.0 + 2f64.powi(-40), 3);
+        let square = x.mul(&x);
+        let expected = Fixed::from_f64(1.0 + 2f64.powi(-39), 3).add(&Fixed::from_f64(2f64.powi(-80), 3));
+        assert_eq!(square, expected);
+        // with two limbs the 2^-80 is cut off
+        let x = x.with_fraction_limbs(2);
+        assert_eq!(x.mul(&x), Fixed::from_f64(1.0 + 2f64.powi(-39), 2));
+    }
+
+    #[test]
+    fn widening_keeps_the_value() {
+        let x = Fixed::from_f64(-1.0 - 2f64.powi(-60), 2);
+        let wide = x.with_fraction_limbs(5);
+        assert_eq!(wide.fraction_limbs(), 5);
+        assert_eq!(wide.with_fraction_limbs(2), x);
+        assert_eq!(wide.to_f64(), x.to_f64());
+        assert!(wide.negative);
+    }
+
+    #[test]
+    fn narrowing_truncates_towards_zero() {
+        let x = Fixed::from_f64(-2f64.powi(-40), 3);
+        let narrow = x.with_fraction_limbs(1);
+        assert_eq!(narrow, Fixed::zero(1));
+        let y = Fixed::from_f64(-1.5 - 2f64.powi(-40), 3).with_fraction_limbs(1);
+        assert_eq!(y.to_f64(), -1.5);
+    }
+
+    #[test]
+    fn limbs_are_capped() {
+        assert_eq!(Fixed::zero(100).fraction_limbs(), MAX_FRACTION_LIMBS);
+    }
+
+    #[test]
+    fn decimals_are_truncated() {
+        assert_eq!(fixed(0.0).to_decimal(3), "0.000");
+        assert_eq!(fixed(1.5).to_decimal(0), "1");
+        assert_eq!(fixed(-2.25).to_decimal(4), "-2.2500");
+        assert_eq!(fixed(0.1).to_decimal(5), "0.10000");
+        assert_eq!(fixed(2.0 / 3.0).to_decimal(4), "0.6666");
+        assert_eq!(fixed(-0.001).to_decimal(2), "-0.00");
+        assert_eq!(Fixed::from_f64(4294967296.5, 1).to_decimal(1), "4294967296.5");
+    }
+
+    #[test]
+    fn decimals_go_past_f64() {
+        // 1 + 2^-100 has 100 exact decimals, more than an f64 could print
+        let x = Fixed::from_f64(1.0, 4).add(&Fixed::from_f64(2f64.powi(-100), 4));
+        assert_eq!(x.to_decimal(31), "1.0000000000000000000000000000007");
+    }
+
+    #[test]
+    fn complex_parts_follow_the_scalars() {
+        let a = FixedComplex::from_f64(Complex::new(1.5, -2.0), 3);
+        let b = FixedComplex::from_f64(Complex::new(-0.5, 0.25), 3);
+        assert_eq!(a.add(&b).to_f64(), Complex::new(1.0, -1.75));
+        assert_eq!(a.sub(&b).to_f64(), Complex::new(2.0, -2.25));
+        assert_eq!(a.with_fraction_limbs(5).fraction_limbs(), 5);
+    }
+}
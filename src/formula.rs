@@ -0,0 +1,654 @@
+extern crate num;
+use num::complex::Complex;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::fractal::{DefaultView, Escape, Fractal};
+use crate::mandelbrot::{self, BAILOUT_RADIUS};
+
+// a formula compiled down to nested closures, called with z and c
+type Compiled = Box<dyn Fn(Complex<f64>, Complex<f64>) -> Complex<f64> + Send + Sync>;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Variable {
+    Z,
+    C,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Log,
+    Sqrt,
+    Conj,
+    Abs,
+    Re,
+    Im,
+    Arg,
+}
+
+// real values are complex values with no imaginary part, they can be used anywhere a complex one can.
+// the difference matters where only a real number makes sense, like the bailout radius
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Type {
+    Real,
+    Complex,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Number(Complex<f64>),
+    Variable(Variable, usize), // with the column it was written at
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Call(Function, Box<Expr>),
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        let function = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "log" => Function::Log,
+            "sqrt" => Function::Sqrt,
+            "conj" => Function::Conj,
+            "abs" => Function::Abs,
+            "re" => Function::Re,
+            "im" => Function::Im,
+            "arg" => Function::Arg,
+            _ => return None,
+        };
+        Some(function)
+    }
+    fn result_type(self, argument: Type) -> Type {
+        match self {
+            Function::Abs | Function::Re | Function::Im | Function::Arg => Type::Real,
+            Function::Conj => argument,
+            _ => Type::Complex,
+        }
+    }
+    // log and sqrt use the principal branch, like every other power in the crate
+    fn apply(self) -> fn(Complex<f64>) -> Complex<f64> {
+        match self {
+            Function::Sin => |z| z.sin(),
+            Function::Cos => |z| z.cos(),
+            Function::Tan => |z| z.tan(),
+            Function::Sinh => |z| z.sinh(),
+            Function::Cosh => |z| z.cosh(),
+            Function::Tanh => |z| z.tanh(),
+            Function::Exp => |z| z.exp(),
+            Function::Log => |z| z.ln(),
+            Function::Sqrt => |z| z.sqrt(),
+            Function::Conj => |z| z.conj(),
+            Function::Abs => |z| Complex::new(z.norm(), 0.0),
+            Function::Re => |z| Complex::new(z.re, 0.0),
+            Function::Im => |z| Complex::new(z.im, 0.0),
+            Function::Arg => |z| Complex::new(z.arg(), 0.0),
+        }
+    }
+}
+
+impl Expr {
+    // the type of the expression, or an error if it uses a variable that is not in scope
+    pub fn check(&self, scope: &[Variable]) -> Result<Type> {
+        match self {
+            Expr::Number(value) if value.im == 0.0 => Ok(Type::Real),
+            Expr::Number(_) => Ok(Type::Complex),
+            Expr::Variable(variable, column) => {
+                if !scope.contains(variable) {
+                    let name = match variable {
+                        Variable::Z => "z",
+                        Variable::C => "c",
+                    };
+                    return Err(parse_error(*column, &format!("{} cannot be used here", name)));
+                }
+                Ok(Type::Complex)
+            }
+            Expr::Negate(operand) => operand.check(scope),
+            Expr::Binary(operator, left, right) => {
+                let (left, right) = (left.check(scope)?, right.check(scope)?);
+                // powers of negative reals leave the real line, so only the other operators keep reals real
+                if left == Type::Real && right == Type::Real && *operator != Operator::Power {
+                    return Ok(Type::Real);
+                }
+                Ok(Type::Complex)
+            }
+            Expr::Call(function, argument) => Ok(function.result_type(argument.check(scope)?)),
+        }
+    }
+    fn is_constant(&self) -> bool {
+        match self {
+            Expr::Number(_) => true,
+            Expr::Variable(..) => false,
+            Expr::Negate(operand) => operand.is_constant(),
+            Expr::Binary(_, left, right) => left.is_constant() && right.is_constant(),
+            Expr::Call(_, argument) => argument.is_constant(),
+        }
+    }
+    // constant parts are evaluated once here instead of for every point and iteration
+    pub fn compile(&self) -> Compiled {
+        if self.is_constant() {
+            let value = self.compile_nodes()(Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+            return Box::new(move |_, _| value);
+        }
+        self.compile_nodes()
+    }
+    fn compile_nodes(&self) -> Compiled {
+        match self {
+            Expr::Number(value) => {
+                let value = *value;
+                Box::new(move |_, _| value)
+            }
+            Expr::Variable(Variable::Z, _) => Box::new(|z, _| z),
+            Expr::Variable(Variable::C, _) => Box::new(|_, c| c),
+            Expr::Negate(operand) => {
+                let operand = operand.compile();
+                Box::new(move |z, c| -operand(z, c))
+            }
+            Expr::Binary(Operator::Power, base, exponent) if exponent.is_constant() => {
+                let base = base.compile();
+                let exponent = exponent.compile()(Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+                Box::new(move |z, c| mandelbrot::power(base(z, c), exponent))
+            }
+            Expr::Binary(operator, left, right) => {
+                let (left, right) = (left.compile(), right.compile());
+                match operator {
+                    Operator::Add => Box::new(move |z, c| left(z, c) + right(z, c)),
+                    Operator::Subtract => Box::new(move |z, c| left(z, c) - right(z, c)),
+                    Operator::Multiply => Box::new(move |z, c| left(z, c) * right(z, c)),
+                    Operator::Divide => Box::new(move |z, c| left(z, c) / right(z, c)),
+                    Operator::Power => Box::new(move |z, c| mandelbrot::power(left(z, c), right(z, c))),
+                }
+            }
+            Expr::Call(function, argument) => {
+                let (function, argument) = (function.apply(), argument.compile());
+                Box::new(move |z, c| function(argument(z, c)))
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(f64),
+    Imaginary(f64), // a number directly followed by i, like 2i
+    Name(String),
+    Symbol(char),
+}
+
+// tokens with the column they start at, counting characters from 1
+fn tokenize(source: &str, first_column: usize) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let column = first_column + index;
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            // an exponent, but only if a digit follows, so that 2e is not eaten as a number
+            if index + 1 < chars.len() && (chars[index] == 'e' || chars[index] == 'E') {
+                let mut end = index + 1;
+                if chars[end] == '+' || chars[end] == '-' {
+                    end += 1;
+                }
+                if end < chars.len() && chars[end].is_ascii_digit() {
+                    index = end;
+                    while index < chars.len() && chars[index].is_ascii_digit() {
+                        index += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..index].iter().collect();
+            let value = text
+                .parse::<f64>()
+                .map_err(|_| parse_error(column, &format!("'{}' is not a number", text)))?;
+            let imaginary = index < chars.len()
+                && chars[index] == 'i'
+                && !chars.get(index + 1).is_some_and(|c| c.is_alphanumeric() || *c == '_');
+            if imaginary {
+                index += 1;
+                tokens.push((column, Token::Imaginary(value)));
+            } else {
+                tokens.push((column, Token::Number(value)));
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            tokens.push((column, Token::Name(chars[start..index].iter().collect())));
+        } else if "+-*/^()".contains(c) {
+            index += 1;
+            tokens.push((column, Token::Symbol(c)));
+        } else {
+            return Err(parse_error(column, &format!("unexpected '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+// recursive descent over the usual precedence: + and -, then * and /, then unary minus, then ^
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end_column: usize, // where errors about a missing token at the end point to
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+    fn column(&self) -> usize {
+        self.tokens.get(self.position).map_or(self.end_column, |(column, _)| *column)
+    }
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+    fn expression(&mut self) -> Result<Expr> {
+        let mut left = self.term()?;
+        loop {
+            let operator = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Subtract
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.term()?));
+        }
+    }
+    fn term(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = if self.eat('*') {
+                Operator::Multiply
+            } else if self.eat('/') {
+                Operator::Divide
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat('-') {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+    // right associative, so z^2^3 is z^(2^3), and -z^2 is -(z^2)
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary(Operator::Power, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+    fn atom(&mut self) -> Result<Expr> {
+        let column = self.column();
+        let token = match self.tokens.get(self.position) {
+            Some((_, token)) => token.clone(),
+            None => return Err(parse_error(column, "expected a value")),
+        };
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(Expr::Number(Complex::new(value, 0.0))),
+            Token::Imaginary(value) => Ok(Expr::Number(Complex::new(0.0, value))),
+            Token::Symbol('(') => {
+                let inner = self.expression()?;
+                if !self.eat(')') {
+                    return Err(parse_error(self.column(), "expected ')'"));
+                }
+                Ok(inner)
+            }
+            Token::Name(name) => match name.as_str() {
+                "z" => Ok(Expr::Variable(Variable::Z, column)),
+                "c" => Ok(Expr::Variable(Variable::C, column)),
+                "i" => Ok(Expr::Number(Complex::new(0.0, 1.0))),
+                "pi" => Ok(Expr::Number(Complex::new(std::f64::consts::PI, 0.0))),
+                "e" => Ok(Expr::Number(Complex::new(std::f64::consts::E, 0.0))),
+                _ => {
+                    let function = Function::from_name(&name)
+                        .ok_or_else(|| parse_error(column, &format!("unknown name '{}'", name)))?;
+                    if !self.eat('(') {
+                        return Err(parse_error(self.column(), &format!("expected '(' after {}", name)));
+                    }
+                    let argument = self.expression()?;
+                    if !self.eat(')') {
+                        return Err(parse_error(self.column(), "expected ')'"));
+                    }
+                    Ok(Expr::Call(function, Box::new(argument)))
+                }
+            },
+            Token::Symbol(symbol) => Err(parse_error(column, &format!("unexpected '{}'", symbol))),
+        }
+    }
+}
+
+// parses the expression in source, whose first character is at first_column of the whole line
+pub fn parse_expression(source: &str, first_column: usize) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(source, first_column)?,
+        position: 0,
+        end_column: first_column + source.chars().count(),
+    };
+    let expr = parser.expression()?;
+    if parser.position < parser.tokens.len() {
+        return Err(parse_error(parser.column(), "expected an operator"));
+    }
+    Ok(expr)
+}
+
+fn parse_error(column: usize, message: &str) -> Error {
+    Error::Parse {
+        column,
+        message: String::from(message),
+    }
+}
+
+// an iteration typed in by the user, like "z = z^3 + c*sin(z); z0 = c; bailout = 4".
+// z is required, z0 defaults to 0 and the bailout radius to the one the built-in fractals use
+pub struct Formula {
+    source: String,
+    iterate: Compiled,
+    start: Compiled,
+    bailout: f64,
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Formula> {
+        let mut iterate = None;
+        let mut start = None;
+        let mut bailout = None;
+        let mut bailout_column = 1;
+        let mut column = 1;
+        for statement in source.split(';') {
+            let statement_column = column;
+            column += statement.chars().count() + 1;
+            if statement.trim().is_empty() {
+                continue;
+            }
+            let (name, expr) = match statement.split_once('=') {
+                Some(parts) => parts,
+                None => return Err(parse_error(statement_column, "expected 'name = expression'")),
+            };
+            let name_column = statement_column + name.chars().take_while(|c| c.is_whitespace()).count();
+            let expr_column = statement_column + name.chars().count() + 1;
+            // where the expression itself starts, for errors about the expression as a whole
+            let value_column = expr_column + expr.chars().take_while(|c| c.is_whitespace()).count();
+            let expr = parse_expression(expr, expr_column)?;
+            let (slot, scope): (&mut Option<Expr>, &[Variable]) = match name.trim() {
+                "z" => (&mut iterate, &[Variable::Z, Variable::C]),
+                "z0" => (&mut start, &[Variable::C]),
+                "bailout" => (&mut bailout, &[]),
+                other => return Err(parse_error(name_column, &format!("'{}' is not z, z0 or bailout", other))),
+            };
+            if slot.is_some() {
+                return Err(parse_error(name_column, &format!("{} is given twice", name.trim())));
+            }
+            let expr_type = expr.check(scope)?;
+            if name.trim() == "bailout" {
+                if expr_type != Type::Real {
+                    return Err(parse_error(value_column, "the bailout has to be a real number"));
+                }
+                bailout_column = value_column;
+            }
+            *slot = Some(expr);
+        }
+        let iterate = iterate.ok_or_else(|| parse_error(1, "the formula needs a 'z = ...' iteration"))?;
+        let bailout = match bailout {
+            Some(expr) => expr.compile()(Complex::new(0.0, 0.0), Complex::new(0.0, 0.0)).re,
+            None => BAILOUT_RADIUS,
+        };
+        if !(bailout > 0.0 && bailout.is_finite()) {
+            return Err(parse_error(bailout_column, "the bailout has to be positive"));
+        }
+        Ok(Formula {
+            source: String::from(source.trim()),
+            iterate: iterate.compile(),
+            start: start.map_or_else(|| Box::new(|_, _| Complex::new(0.0, 0.0)) as Compiled, |expr| expr.compile()),
+            bailout,
+        })
+    }
+}
+
+impl Fractal for Formula {
+    fn name(&self) -> String {
+        String::from("Formula")
+    }
+    // the shape of the set is not known in advance, so the view only tries to fit the bailout circle
+    fn default_view(&self) -> DefaultView {
+        DefaultView {
+            center: Complex::new(0.0, 0.0),
+            radius: f64::min(self.bailout, 2.0),
+        }
+    }
+    fn describe(&self) -> Vec<String> {
+        vec![format!("Formula: {}", self.source)]
+    }
+    fn source(&self) -> Option<String> {
+        Some(self.source.clone())
+    }
+    fn edit(&self, source: &str) -> Result<Arc<dyn Fractal>> {
+        Ok(Arc::new(Formula::parse(source)?))
+    }
+    fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape {
+        let mut z = (self.start)(c, c);
+        let mut z_norm = z.norm_sqr();
+        for i in 0..n_iter {
+            z = (self.iterate)(z, c);
+            z_norm = z.norm_sqr();
+            // divisions by zero and overflows leave the plane as surely as escaping does
+            if z_norm.is_nan() || z_norm.is_infinite() {
+                return Escape {
+                    smooth: i as f64,
+                    ..Escape::escaped(i, f64::MAX, 2.0)
+                };
+            }
+            // the degree of the formula is unknown, so the smooth count assumes a quadratic one
+            if z_norm > self.bailout * self.bailout {
+                return Escape::escaped(i, z_norm, 2.0);
+            }
+        }
+        Escape::bounded(n_iter, z_norm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Expr {
+        parse_expression(source, 1).unwrap()
+    }
+
+    fn number(re: f64, im: f64) -> Box<Expr> {
+        Box::new(Expr::Number(Complex::new(re, im)))
+    }
+
+    fn variable(variable: Variable, column: usize) -> Box<Expr> {
+        Box::new(Expr::Variable(variable, column))
+    }
+
+    // the column and message of a parse error, so tests can check where the prompt's caret would point
+    fn error<T>(result: Result<T>) -> (usize, String) {
+        match result {
+            Err(Error::Parse { column, message }) => (column, message),
+            Err(other) => panic!("expected a parse error, got {}", other),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    fn formula_error(source: &str) -> (usize, String) {
+        error(Formula::parse(source))
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        let expected = Expr::Binary(
+            Operator::Add,
+            number(1.0, 0.0),
+            Box::new(Expr::Binary(Operator::Multiply, number(2.0, 0.0), number(3.0, 0.0))),
+        );
+        assert_eq!(parse("1 + 2*3"), expected);
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        let expected = Expr::Binary(
+            Operator::Subtract,
+            Box::new(Expr::Binary(Operator::Subtract, number(1.0, 0.0), number(2.0, 0.0))),
+            number(3.0, 0.0),
+        );
+        assert_eq!(parse("1-2-3"), expected);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        let expected = Expr::Binary(
+            Operator::Power,
+            variable(Variable::Z, 1),
+            Box::new(Expr::Binary(Operator::Power, number(2.0, 0.0), number(3.0, 0.0))),
+        );
+        assert_eq!(parse("z^2^3"), expected);
+    }
+
+    #[test]
+    fn power_binds_tighter_than_unary_minus() {
+        let expected = Expr::Negate(Box::new(Expr::Binary(Operator::Power, variable(Variable::Z, 2), number(2.0, 0.0))));
+        assert_eq!(parse("-z^2"), expected);
+        // a minus in the exponent belongs to the exponent
+        let expected = Expr::Binary(Operator::Power, variable(Variable::Z, 1), Box::new(Expr::Negate(number(2.0, 0.0))));
+        assert_eq!(parse("z^-2"), expected);
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let expected = Expr::Binary(
+            Operator::Multiply,
+            Box::new(Expr::Binary(Operator::Add, number(1.0, 0.0), number(2.0, 0.0))),
+            number(3.0, 0.0),
+        );
+        assert_eq!(parse("(1 + 2) * 3"), expected);
+    }
+
+    #[test]
+    fn compiled_expressions_evaluate_in_precedence_order() {
+        let zero = Complex::new(0.0, 0.0);
+        let value = |source: &str| parse(source).compile()(zero, zero);
+        assert_eq!(value("2^3^2").re, 512.0);
+        assert_eq!(value("-2^2").re, -4.0);
+        assert_eq!(value("1 + 2*3 - 4/2").re, 5.0);
+    }
+
+    #[test]
+    fn imaginary_and_exponent_literals() {
+        assert_eq!(parse("2i"), *number(0.0, 2.0));
+        assert_eq!(parse("2e-3"), *number(2e-3, 0.0));
+        assert_eq!(parse("1.5E+2"), *number(150.0, 0.0));
+        assert_eq!(parse("2.5e3i"), *number(0.0, 2500.0));
+        assert_eq!(parse("i"), *number(0.0, 1.0));
+    }
+
+    #[test]
+    fn an_e_without_digits_is_not_an_exponent() {
+        // 2e is the number 2 followed by the name e, which needs an operator in between
+        assert_eq!(error(parse_expression("2e", 1)), (2, String::from("expected an operator")));
+        assert_eq!(parse("2*e"), Expr::Binary(Operator::Multiply, number(2.0, 0.0), number(std::f64::consts::E, 0.0)));
+    }
+
+    #[test]
+    fn an_i_starting_a_name_is_not_imaginary() {
+        assert_eq!(error(parse_expression("2im(z)", 1)), (2, String::from("expected an operator")));
+    }
+
+    #[test]
+    fn types_follow_the_operands() {
+        let scope = [Variable::Z, Variable::C];
+        assert_eq!(parse("2 * 3 - 1").check(&scope).unwrap(), Type::Real);
+        assert_eq!(parse("2i").check(&scope).unwrap(), Type::Complex);
+        assert_eq!(parse("abs(z) + re(c)").check(&scope).unwrap(), Type::Real);
+        assert_eq!(parse("conj(2)").check(&scope).unwrap(), Type::Real);
+        // a negative real to a real power can leave the real line
+        assert_eq!(parse("2^0.5").check(&scope).unwrap(), Type::Complex);
+    }
+
+    #[test]
+    fn z_cannot_be_used_in_z0() {
+        assert_eq!(formula_error("z = z^2 + c; z0 = c + z"), (23, String::from("z cannot be used here")));
+    }
+
+    #[test]
+    fn variables_cannot_be_used_in_the_bailout() {
+        assert_eq!(formula_error("z = z^2 + c; bailout = c"), (24, String::from("c cannot be used here")));
+    }
+
+    #[test]
+    fn the_bailout_has_to_be_real() {
+        assert_eq!(formula_error("z = z^2 + c; bailout = 2 + i"), (24, String::from("the bailout has to be a real number")));
+        assert_eq!(formula_error("z = z^2 + c; bailout = -2"), (24, String::from("the bailout has to be positive")));
+        // the column is that of the bailout's expression, wherever the statement is
+        assert_eq!(formula_error("bailout =   1/0; z = z^2 + c"), (13, String::from("the bailout has to be positive")));
+        assert_eq!(Formula::parse("z = z^2 + c; bailout = abs(3 + 4i)").unwrap().bailout, 5.0);
+    }
+
+    #[test]
+    fn error_columns_point_at_the_offending_character() {
+        assert_eq!(error(parse_expression("z + $", 1)), (5, String::from("unexpected '$'")));
+        assert_eq!(error(parse_expression("z + foo(z)", 1)), (5, String::from("unknown name 'foo'")));
+        assert_eq!(error(parse_expression("sin z", 1)), (5, String::from("expected '(' after sin")));
+        assert_eq!(error(parse_expression("z * )", 1)), (5, String::from("unexpected ')'")));
+        // missing tokens at the end point just past the last character
+        assert_eq!(error(parse_expression("(z + c", 1)), (7, String::from("expected ')'")));
+        assert_eq!(error(parse_expression("z +", 1)), (4, String::from("expected a value")));
+    }
+
+    #[test]
+    fn error_columns_count_from_the_start_of_the_line() {
+        assert_eq!(error(parse_expression("z + $", 10)), (14, String::from("unexpected '$'")));
+        // the second statement starts after "z = z^2 + c;", its expression after "z0 ="
+        assert_eq!(formula_error("z = z^2 + c; z0 = c $"), (21, String::from("unexpected '$'")));
+        assert_eq!(formula_error("z = z^2 + c;  w = c"), (15, String::from("'w' is not z, z0 or bailout")));
+        assert_eq!(formula_error("z = c; z = z"), (8, String::from("z is given twice")));
+        assert_eq!(formula_error("z = c; z0"), (7, String::from("expected 'name = expression'")));
+        assert_eq!(formula_error("z0 = c"), (1, String::from("the formula needs a 'z = ...' iteration")));
+    }
+
+    #[test]
+    fn columns_count_characters_rather_than_bytes() {
+        // a non-breaking space is whitespace two bytes long
+        assert_eq!(error(parse_expression("z +\u{a0}\u{a0}$", 1)), (6, String::from("unexpected '$'")));
+        assert_eq!(formula_error("z = c;\u{a0}z0 = c $"), (15, String::from("unexpected '$'")));
+        assert_eq!(error(parse_expression("z × c", 1)), (3, String::from("unexpected '×'")));
+    }
+}
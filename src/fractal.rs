@@ -0,0 +1,183 @@
+use num::complex::Complex;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::fixed::FixedComplex;
+use crate::formula::Formula;
+use crate::mandelbrot::{Mandelbrot, QUADRATIC};
+use crate::newton::Newton;
+use crate::variants::{Fold, Variant};
+
+pub const MIN_ITER: i32 = 15;
+
+// the raw result of iterating a single point, kept in the buffer so that
+// glyphs and colours can be chosen later without recomputing anything
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Escape {
+    pub iterations: u16, // iteration at which the orbit escaped, or n_iter if it never did
+    pub smooth: f64,     // normalized iteration count n + 1 - log2(log|z|), equal to iterations for interior points
+    pub norm_sqr: f64,   // |z|^2 at the last computed iteration
+    pub interior: bool,  // true if the point is considered part of the set
+    pub basin: Option<u16>, // the attractor the orbit converged to, for convergence based fractals
+    pub period: Option<u16>, // length of the cycle an interior orbit settled into, or a multiple of it where it settles slowly
+}
+
+impl Escape {
+    // an orbit of z -> z^degree + c that left the bailout circle at iteration i
+    pub fn escaped(i: u16, norm_sqr: f64, degree: f64) -> Escape {
+        let log_z = 0.5 * norm_sqr.ln();
+        Escape {
+            iterations: i,
+            smooth: (i as f64 + 1.0 - log_z.ln() / degree.ln()).max(0.0),
+            norm_sqr,
+            interior: false,
+            basin: None,
+            period: None,
+        }
+    }
+    // an orbit that stayed bounded for all n_iter iterations
+    pub fn bounded(n_iter: u16, norm_sqr: f64) -> Escape {
+        Escape {
+            iterations: n_iter,
+            smooth: n_iter as f64,
+            norm_sqr,
+            interior: true,
+            basin: None,
+            period: None,
+        }
+    }
+    // an orbit known to be bounded because it settled into a cycle, it looks the same as one that ran out of iterations
+    pub fn periodic(n_iter: u16, norm_sqr: f64, period: u16) -> Escape {
+        Escape {
+            period: Some(period),
+            ..Escape::bounded(n_iter, norm_sqr)
+        }
+    }
+    // points in the same region can stand in for each other until the next full render
+    pub fn same_region(&self, other: &Escape) -> bool {
+        self.interior == other.interior && self.basin == other.basin
+    }
+    // the iteration value used for colouring, either banded or continuous
+    pub fn iteration(&self, smooth: bool) -> f64 {
+        if smooth {
+            self.smooth
+        } else {
+            self.iterations as f64
+        }
+    }
+}
+
+// the difference to a reference orbit after `skipped` iterations, as a cubic in dc shared by a whole view.
+// perturbed points start from there instead of from iteration 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Series {
+    pub skipped: u16,
+    pub coefficients: [Complex<f64>; 3], // of dc, dc^2 and dc^3
+}
+
+impl Series {
+    pub const NONE: Series = Series {
+        skipped: 0,
+        coefficients: [Complex::new(0.0, 0.0); 3],
+    };
+    pub fn delta(&self, dc: Complex<f64>) -> Complex<f64> {
+        let [a, b, c] = self.coefficients;
+        ((c * dc + b) * dc + a) * dc
+    }
+}
+
+// a named value a fractal depends on, like the constant of a julia set
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub value: Complex<f64>,
+}
+
+// the region a fractal is first shown in, a circle that has to fit on the screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DefaultView {
+    pub center: Complex<f64>,
+    pub radius: f64,
+}
+
+// an escape-time formula, evaluated independently for every point of the plane.
+// implementations are immutable and shared between the render threads, changing a
+// parameter makes a new fractal
+pub trait Fractal: Send + Sync {
+    fn name(&self) -> String;
+    fn parameters(&self) -> Vec<Parameter> {
+        Vec::new()
+    }
+    // a copy with one parameter changed, None if there is no parameter with that name
+    fn with_parameter(&self, _name: &str, _value: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        None
+    }
+    // the dynamic plane belonging to a point of this parameter plane, if there is one
+    fn julia(&self, _c: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        None
+    }
+    fn default_view(&self) -> DefaultView;
+    // extra lines for the status bar, like the roots a newton fractal converges to
+    fn describe(&self) -> Vec<String> {
+        Vec::new()
+    }
+    // the text this fractal was defined from, None if it cannot be edited at runtime
+    fn source(&self) -> Option<String> {
+        None
+    }
+    // a new fractal from an edited version of source()
+    fn edit(&self, _source: &str) -> Result<Arc<dyn Fractal>> {
+        Err(Error::Parse {
+            column: 1,
+            message: String::from("this fractal cannot be edited"),
+        })
+    }
+    // iterations needed to resolve the detail at a scale, deeper zooms need more
+    fn default_iterations(&self, scale: f64) -> i32 {
+        std::cmp::max(200 * (1 - (0.5 * scale.log10()) as i32), MIN_ITER)
+    }
+    fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape;
+    // true if compute_fixed really computes in fixed point, which is used once f64 cannot resolve the samples
+    fn supports_fixed(&self) -> bool {
+        false
+    }
+    fn compute_fixed(&self, c: &FixedComplex, n_iter: u16) -> Escape {
+        self.compute(c.to_f64(), n_iter)
+    }
+    // true if deep zooms can be rendered by perturbation, a few orbits in fixed point and the rest in f64
+    fn supports_perturbation(&self) -> bool {
+        false
+    }
+    // the orbit of a reference point, computed in fixed point and stored in f64, starting with z_0
+    fn reference_orbit(&self, _c: &FixedComplex, _n_iter: u16) -> Vec<Complex<f64>> {
+        Vec::new()
+    }
+    // how many iterations all points within the probes can skip at once, checked against the probes
+    // themselves, which are given as differences to the reference point
+    fn series(&self, _orbit: &[Complex<f64>], _probes: &[Complex<f64>], _n_iter: u16) -> Series {
+        Series::NONE
+    }
+    // a point dc away from the reference, iterated as a small difference to its orbit from where the series
+    // left off. None if the difference cannot be trusted any more and the point needs another reference
+    fn compute_perturbed(&self, _orbit: &[Complex<f64>], _series: &Series, _dc: Complex<f64>, _n_iter: u16) -> Option<Escape> {
+        None
+    }
+}
+
+// z^3 - 1, whose three basins meet in the classic newton fractal
+const DEFAULT_POLYNOMIAL: &str = "1 0 0 -1";
+// starts at c, since z = 0 is a fixed point of the iteration
+const DEFAULT_FORMULA: &str = "z = z^3 + c*sin(z); z0 = c; bailout = 16";
+
+// every fractal that can be cycled through at runtime, in order
+pub fn registry() -> Vec<Arc<dyn Fractal>> {
+    vec![
+        Arc::new(Mandelbrot { d: QUADRATIC }),
+        Arc::new(Variant { fold: Fold::BurningShip }),
+        Arc::new(Variant { fold: Fold::Tricorn }),
+        Arc::new(Variant { fold: Fold::Celtic }),
+        Arc::new(Variant { fold: Fold::Perpendicular }),
+        Arc::new(Newton::parse(DEFAULT_POLYNOMIAL).expect("the default polynomial parses")),
+        Arc::new(Formula::parse(DEFAULT_FORMULA).expect("the default formula parses")),
+    ]
+}
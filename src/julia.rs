@@ -0,0 +1,53 @@
+extern crate num;
+use num::complex::Complex;
+use std::sync::Arc;
+
+use crate::fractal::{DefaultView, Escape, Fractal, Parameter};
+use crate::mandelbrot::{self, MIN_EXPONENT, QUADRATIC};
+
+// z -> z^d + c with a fixed c, starting from z at the point itself
+pub struct Julia {
+    pub c: Complex<f64>,
+    pub d: Complex<f64>,
+}
+
+impl Fractal for Julia {
+    fn name(&self) -> String {
+        String::from("Julia")
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter { name: "c", value: self.c }, Parameter { name: "d", value: self.d }]
+    }
+    fn with_parameter(&self, name: &str, value: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        match name {
+            "c" => Some(Arc::new(Julia { c: value, d: self.d })),
+            "d" if value.re >= MIN_EXPONENT => Some(Arc::new(Julia { c: self.c, d: value })),
+            _ => None,
+        }
+    }
+    // every julia set of z^2 + c lies within the circle of radius 2, other exponents have their own bound
+    fn default_view(&self) -> DefaultView {
+        let radius = if self.d == QUADRATIC {
+            1.6
+        } else {
+            f64::min(0.8 * mandelbrot::escape_radius(0.0, self.d), 4.0)
+        };
+        DefaultView {
+            center: Complex::new(0.0, 0.0),
+            radius,
+        }
+    }
+    fn compute(&self, z: Complex<f64>, n_iter: u16) -> Escape {
+        let mut z = z;
+        let mut z_norm = z.norm_sqr();
+        let bailout = mandelbrot::bailout_sqr(self.c, self.d);
+        for i in 0..n_iter {
+            z = mandelbrot::power(z, self.d) + self.c;
+            z_norm = z.norm_sqr();
+            if z_norm > bailout {
+                return Escape::escaped(i, z_norm, self.d.re);
+            }
+        }
+        Escape::bounded(n_iter, z_norm)
+    }
+}
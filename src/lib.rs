@@ -0,0 +1,21 @@
+//! Escape-time fractal rendering for the terminal.
+//!
+//! The fractal engine (`fractal`, `mandelbrot`, `viewport`, `render`) works without a terminal,
+//! `render::render_grid` computes a grid of escape data for any viewport. The `terminal` module
+//! holds the interactive frontend pieces, including the generic `cyclic_buffer::Buffer<T>`.
+
+pub mod error;
+pub mod fixed;
+pub mod formula;
+pub mod fractal;
+pub mod julia;
+pub mod mandelbrot;
+pub mod newton;
+pub mod palette;
+pub mod pool;
+pub mod render;
+pub mod shading;
+pub mod stat_bar;
+pub mod terminal;
+pub mod variants;
+pub mod viewport;
@@ -1,102 +1,443 @@
+use num::complex::Complex;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-mod terminal;
 use cyclic_buffer::Direction;
-use terminal::{async_input, cyclic_buffer, screen};
-mod mandelbrot;
-mod stat_bar;
+use async_input::{Event, Key, MouseButton, MouseKind};
+use term_fractals::fractal::{self, Fractal};
+use term_fractals::terminal::prompt::{Prompt, PromptEvent};
+use term_fractals::terminal::{async_input, cyclic_buffer, guard, screen};
+use term_fractals::viewport::Viewport;
+use term_fractals::{error, render, stat_bar};
 
 fn main() {
     if let Err(e) = run() {
         println!("main exited with error: {}", e);
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            println!("  caused by: {}", cause);
+            source = cause.source();
+        }
+    }
+}
+
+// the exponent moves in steps of 1 / EXPONENT_STEPS, small enough that the set visibly morphs instead of jumping
+const EXPONENT_STEPS: f64 = 20.0;
+
+// low enough that the inset keeps up with the pointer
+const INSET_ITER: i32 = 100;
+
+// how often a frame that is still rendering is drawn with the tiles that arrived so far
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+// the view of a plane as it is left, its scale given per terminal cell so that it does not depend on the render mode
+fn save_view(screen: &screen::Screen) -> Viewport {
+    let samples = screen.render_mode.samples_per_cell();
+    Viewport {
+        scale: screen.viewport.scale * samples.0 as f64,
+        ..screen.viewport
     }
 }
 
-fn run() -> Result<(), &'static str> {
-    let mut screen = screen::Screen::new_screen();
+// goes back to where a plane was left, or to the fractal's default view the first time
+fn restore_view(screen: &mut screen::Screen, saved: Option<Viewport>, fractal: &dyn Fractal) {
+    match saved {
+        // the render mode may have changed the sample shape since
+        Some(viewport) => {
+            let samples = screen.render_mode.samples_per_cell();
+            screen.viewport = Viewport {
+                scale: viewport.scale / samples.0 as f64,
+                aspect: screen.viewport.aspect,
+                ..viewport
+            };
+            screen.buffer.clear(cyclic_buffer::Pixel::Recompute);
+        }
+        None => screen.fit_view(fractal.default_view()),
+    }
+}
+
+fn run() -> error::Result<()> {
+    let mut fractals = fractal::registry();
+    let mut current = 0;
+    let mut screen = screen::Screen::new_screen(fractals[current].default_view())?;
 
     screen.clear_screen()?;
 
+    // the fractal on screen, either fractals[current] or the julia set of one of its points
+    let mut fractal = Arc::clone(&fractals[current]);
+    let mut in_julia = false;
+    // every plane remembers where it was left, so switching back returns there
+    let mut views: Vec<Option<Viewport>> = vec![None; fractals.len()];
+    let mut julia_view: Option<Viewport> = None;
+    // the julia set of the point under the pointer, or of the center without one
+    let mut show_inset = false;
+    let mut pointer: Option<(u16, u16)> = None;
     let mut should_end_program = false;
     let zoom_in = 1.2;
     let zoom_out = 1.0 / zoom_in;
-    let move_speed = 2.0 * screen.term_size.0 as f64 / 100.0;
-    let move_speed = std::cmp::max(1, move_speed as u16);
+    // moves are given in terminal cells, the buffer works in samples
+    let move_by = |screen: &mut screen::Screen, direction: Direction, cells: u16| {
+        let (sx, sy) = screen.render_mode.samples_per_cell();
+        match direction {
+            Direction::Left | Direction::Right => screen.on_move(direction, cells * sx),
+            Direction::Up | Direction::Down => screen.on_move(direction, cells * sy),
+        }
+    };
     let mut n_iter_additive: i32 = 0;
     let mut n_iter_step_size: u16;
-    let mut last_action_was_zoom = false;
+    // set after zooms and resizes, where pixels are reused from nearby samples until the next idle moment
+    let mut pixels_are_approximate = false;
+    // cell the left button was last seen at while held, and whether the press turned into a drag
+    let mut drag_from: Option<(u16, u16)> = None;
+    let mut dragged = false;
+    let mut rectangle_from: Option<(u16, u16)> = None;
+    let mut background = render::Background::new();
     loop {
+        // whatever changed made the render in progress outdated, and the inset needs the workers
+        background.cancel();
         screen.clear_screen()?;
-        // render the status bar
-        stat_bar::clear_stat_bar(&mut screen)?;
         let n_iter: i32 = std::cmp::max(
-            (200 * (1 - (0.5 * screen.scale.log10()) as i32)) as i32 + n_iter_additive,
-            mandelbrot::MIN_ITER,
+            fractal.default_iterations(screen.viewport.scale) + n_iter_additive,
+            fractal::MIN_ITER,
         );
-        stat_bar::render_status_bar(&mut screen, n_iter as u16)?;
+        screen.inset = None;
+        if show_inset && !in_julia {
+            let c = match pointer {
+                Some((x, y)) if x < screen.term_size.0 && y < screen.term_size.1 => {
+                    let (x, y) = screen.cell_to_sample(x, y);
+                    screen.get_complex_coords(x, y)?
+                }
+                _ => screen.viewport.center,
+            };
+            if let Some(julia) = fractals[current].julia(c) {
+                let preview_iter = std::cmp::min(n_iter, INSET_ITER) as u16;
+                screen.inset = Some((render::render_preview(&julia, screen.inset_size(), preview_iter)?, preview_iter));
+            }
+        }
 
-        mandelbrot::render_whole_mandelbrot(&mut screen, n_iter as u16)?;
-        screen.render()?;
-        n_iter_step_size = std::cmp::max(-2 * screen.scale.log10() as i32, 1) as u16;
+        background.start(&mut screen, &fractal, n_iter as u16)?;
+        // after the start, so that numbers of the last render are not shown for the one that replaces it
+        stat_bar::render_status_bar(&mut screen, fractal.as_ref(), n_iter as u16);
+        screen.render(n_iter as u16)?;
+        let mut drawn_at = Instant::now();
+        let mut undrawn = false;
+        n_iter_step_size = std::cmp::max(-2 * screen.viewport.scale.log10() as i32, 1) as u16;
         loop {
-            let c = async_input::get_char(&mut screen);
-            match c {
-                None => {
-                    // if previous action was zoom, re-draw the screen
-                    if last_action_was_zoom {
-                        screen.buffer.clear(cyclic_buffer::Pixel::Recompute);
-                        last_action_was_zoom = false;
-                        break;
+            undrawn |= background.receive(&mut screen)?;
+            if undrawn && (!background.is_running() || drawn_at.elapsed() >= PROGRESS_INTERVAL) {
+                screen.render(n_iter as u16)?;
+                screen.flush_screen()?;
+                drawn_at = Instant::now();
+                undrawn = false;
+            }
+            if screen.poll_resume() {
+                break;
+            }
+            if let Some(exact) = screen.poll_resize()? {
+                pixels_are_approximate |= !exact;
+                break;
+            }
+            let events = async_input::get_events(&mut screen)?;
+            // once the input has settled and the render is done, approximated pixels are recomputed properly
+            if events.is_empty() && pixels_are_approximate && !background.is_running() {
+                screen.invalidate()?;
+                pixels_are_approximate = false;
+                break;
+            }
+            // several keys can arrive at once, all of them are applied before the next frame
+            let mut redraw = false;
+            for event in events {
+                // while a prompt is open, everything typed goes into it
+                if let Some(prompt) = screen.prompt.as_mut() {
+                    let submitted = match prompt.handle(&event) {
+                        PromptEvent::Editing => None,
+                        PromptEvent::Submit => Some(prompt.text.clone()),
+                        PromptEvent::Cancel => {
+                            screen.prompt = None;
+                            None
+                        }
+                    };
+                    if let Some(text) = submitted {
+                        match fractal.edit(&text) {
+                            Ok(edited) => {
+                                if !in_julia {
+                                    fractals[current] = Arc::clone(&edited);
+                                }
+                                fractal = edited;
+                                screen.fit_view(fractal.default_view());
+                                screen.prompt = None;
+                                n_iter_additive = 0;
+                            }
+                            Err(error::Error::Parse { column, message }) => {
+                                if let Some(prompt) = screen.prompt.as_mut() {
+                                    prompt.error = Some((column, message));
+                                }
+                            }
+                            Err(e) => return Err(e),
+                        }
                     }
+                    redraw |= !matches!(event, Event::Mouse(_));
+                    continue;
                 }
-                Some('q') => {
-                    should_end_program = true;
-                    break;
-                }
-                // movement controlls
-                Some('l') => {
-                    screen.on_move(Direction::Left, move_speed)?;
-                    break;
-                }
-                Some('k') => {
-                    screen.on_move(Direction::Down, move_speed)?;
-                    break;
-                }
-                Some('j') => {
-                    screen.on_move(Direction::Up, move_speed)?;
-                    break;
-                }
-                Some('h') => {
-                    screen.on_move(Direction::Right, move_speed)?;
-                    break;
-                }
-                // zoom control
-                Some('x') => {
-                    screen.on_zoom(zoom_out)?;
-                    last_action_was_zoom = true;
-                    break;
-                }
-                Some('z') => {
-                    screen.on_zoom(zoom_in)?;
-                    last_action_was_zoom = true;
-                    break;
-                }
-                // iteration control
-                Some('n') => {
-                    n_iter_additive += n_iter_step_size as i32;
-                    screen.buffer.clear(cyclic_buffer::Pixel::Recompute);
-                    break;
-                }
-                Some('m') => {
-                    if n_iter - n_iter_step_size as i32 > mandelbrot::MIN_ITER {
-                        n_iter_additive -= n_iter_step_size as i32;
-                        screen.buffer.clear(cyclic_buffer::Pixel::Recompute);
+                let (key, modifiers) = match event {
+                    Event::Key(key, modifiers) => (key, modifiers),
+                    Event::Paste(_) => continue,
+                    Event::Mouse(mouse) => {
+                        pointer = Some((mouse.x, mouse.y));
+                        redraw |= match mouse.kind {
+                            MouseKind::Press(MouseButton::Left) => {
+                                drag_from = Some((mouse.x, mouse.y));
+                                dragged = false;
+                                false
+                            }
+                            // the picture follows the pointer, moving by whole cells so computed pixels are kept
+                            MouseKind::Drag(MouseButton::Left) => match drag_from {
+                                Some((x, y)) if (x, y) != (mouse.x, mouse.y) => {
+                                    let (sx, sy) = screen.render_mode.samples_per_cell();
+                                    let dx = (x as i32 - mouse.x as i32) * sx as i32;
+                                    let dy = (y as i32 - mouse.y as i32) * sy as i32;
+                                    screen.move_by_samples(dx, dy)?;
+                                    drag_from = Some((mouse.x, mouse.y));
+                                    dragged = true;
+                                    true
+                                }
+                                _ => false,
+                            },
+                            MouseKind::Release(MouseButton::Left) => {
+                                drag_from = None;
+                                if dragged {
+                                    false
+                                } else {
+                                    let (x, y) = screen.clamp_cell(mouse.x, mouse.y);
+                                    let (x, y) = screen.cell_to_sample(x, y);
+                                    screen.recenter_on(x, y)?;
+                                    true
+                                }
+                            }
+                            // the point under the pointer stays where it is
+                            MouseKind::ScrollUp | MouseKind::ScrollDown => {
+                                let (x, y) = screen.cell_to_sample(mouse.x, mouse.y);
+                                let zoom = if mouse.kind == MouseKind::ScrollUp { zoom_in } else { zoom_out };
+                                screen.on_zoom(zoom, (x as f64, y as f64))?;
+                                pixels_are_approximate = true;
+                                true
+                            }
+                            // dragging with the right button selects a rectangle that is zoomed into on release
+                            MouseKind::Press(MouseButton::Right) => {
+                                rectangle_from = Some((mouse.x, mouse.y));
+                                false
+                            }
+                            // both corners are kept on screen, the release can be reported past its edge
+                            MouseKind::Release(MouseButton::Right) => match rectangle_from.take() {
+                                Some((x, y)) => {
+                                    let (x, y) = screen.clamp_cell(x, y);
+                                    let (to_x, to_y) = screen.clamp_cell(mouse.x, mouse.y);
+                                    if x.abs_diff(to_x) > 1 && y.abs_diff(to_y) > 1 {
+                                        let (x0, y0) = screen.cell_to_sample(std::cmp::min(x, to_x), std::cmp::min(y, to_y));
+                                        let (x1, y1) = screen.cell_to_sample(std::cmp::max(x, to_x), std::cmp::max(y, to_y));
+                                        let (w, h) = screen.sample_size;
+                                        screen.recenter_on((x0 + x1) / 2, (y0 + y1) / 2)?;
+                                        let zoom = f64::min(w as f64 / (x1 - x0) as f64, h as f64 / (y1 - y0) as f64);
+                                        screen.on_zoom(zoom, screen.middle())?;
+                                        pixels_are_approximate = true;
+                                        true
+                                    } else {
+                                        false
+                                    }
+                                }
+                                None => false,
+                            },
+                            // the inset follows the pointer
+                            MouseKind::Move => show_inset && !in_julia,
+                            // a middle click opens the julia set of the point under the pointer
+                            MouseKind::Press(MouseButton::Middle) if !in_julia => {
+                                let (x, y) = screen.clamp_cell(mouse.x, mouse.y);
+                                let (x, y) = screen.cell_to_sample(x, y);
+                                let c = screen.get_complex_coords(x, y)?;
+                                match fractals[current].julia(c) {
+                                    Some(julia) => {
+                                        views[current] = Some(save_view(&screen));
+                                        in_julia = true;
+                                        fractal = julia;
+                                        restore_view(&mut screen, julia_view, fractal.as_ref());
+                                        true
+                                    }
+                                    None => false,
+                                }
+                            }
+                            _ => false,
+                        };
+                        continue;
+                    }
+                };
+                // shift moves further, mostly useful with the arrow keys
+                let move_speed = std::cmp::max(1, (2.0 * screen.term_size.0 as f64 / 100.0) as u16);
+                let cells = if modifiers.shift { 4 * move_speed } else { move_speed };
+                redraw |= match key {
+                    Key::Char('q') => {
+                        should_end_program = true;
+                        true
+                    }
+                    Key::Char('c') if modifiers.ctrl => {
+                        should_end_program = true;
+                        true
+                    }
+                    Key::Char('z') if modifiers.ctrl => {
+                        screen.flush_screen()?;
+                        guard::suspend();
+                        false
+                    }
+                    // movement controlls
+                    Key::Char('l') | Key::Right => {
+                        move_by(&mut screen, Direction::Left, cells)?;
+                        true
+                    }
+                    Key::Char('k') | Key::Up => {
+                        move_by(&mut screen, Direction::Down, cells)?;
+                        true
+                    }
+                    Key::Char('j') | Key::Down => {
+                        move_by(&mut screen, Direction::Up, cells)?;
+                        true
+                    }
+                    Key::Char('h') | Key::Left => {
+                        move_by(&mut screen, Direction::Right, cells)?;
+                        true
                     }
+                    // zoom control
+                    Key::Char('x') | Key::Char('-') | Key::PageDown => {
+                        screen.on_zoom(zoom_out, screen.middle())?;
+                        pixels_are_approximate = true;
+                        true
+                    }
+                    Key::Char('z') | Key::Char('+') | Key::Char('=') | Key::PageUp => {
+                        screen.on_zoom(zoom_in, screen.middle())?;
+                        pixels_are_approximate = true;
+                        true
+                    }
+                    // iteration control
+                    Key::Char('n') => {
+                        n_iter_additive += n_iter_step_size as i32;
+                        screen.invalidate()?;
+                        true
+                    }
+                    Key::Char('m') => {
+                        if n_iter - n_iter_step_size as i32 > fractal::MIN_ITER {
+                            n_iter_additive -= n_iter_step_size as i32;
+                            screen.invalidate()?;
+                        }
+                        true
+                    }
+                    // display control, the buffer keeps its values so nothing is recomputed
+                    Key::Char('r') => {
+                        screen.shading = screen.shading.next();
+                        true
+                    }
+                    Key::Char('p') => {
+                        screen.next_palette();
+                        true
+                    }
+                    Key::Char('s') => {
+                        screen.smooth = !screen.smooth;
+                        true
+                    }
+                    Key::Char('v') => {
+                        screen.next_render_mode();
+                        true
+                    }
+                    // cycling always lands in the parameter plane of the next fractal
+                    Key::Char('f') => {
+                        if in_julia {
+                            julia_view = Some(save_view(&screen));
+                            in_julia = false;
+                        } else {
+                            views[current] = Some(save_view(&screen));
+                            current = (current + 1) % fractals.len();
+                        }
+                        fractal = Arc::clone(&fractals[current]);
+                        restore_view(&mut screen, views[current], fractal.as_ref());
+                        n_iter_additive = 0;
+                        true
+                    }
+                    // switches between the parameter plane and the julia set of its center
+                    Key::Char('t') => {
+                        if in_julia {
+                            julia_view = Some(save_view(&screen));
+                            in_julia = false;
+                            fractal = Arc::clone(&fractals[current]);
+                            restore_view(&mut screen, views[current], fractal.as_ref());
+                            true
+                        } else if let Some(julia) = fractals[current].julia(screen.viewport.center) {
+                            views[current] = Some(save_view(&screen));
+                            in_julia = true;
+                            fractal = julia;
+                            restore_view(&mut screen, julia_view, fractal.as_ref());
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    // steps the exponent of fractals that have one, both planes share it
+                    Key::Char('[') | Key::Char(']') | Key::Char('{') | Key::Char('}') => {
+                        let step = match key {
+                            Key::Char('[') => Complex::new(-1.0, 0.0),
+                            Key::Char(']') => Complex::new(1.0, 0.0),
+                            Key::Char('{') => Complex::new(0.0, -1.0),
+                            _ => Complex::new(0.0, 1.0),
+                        };
+                        // snapped to the grid of steps, so stepping back lands exactly on integer exponents
+                        let stepped_exponent = |d: Complex<f64>| {
+                            let steps = d * EXPONENT_STEPS + step;
+                            Complex::new(steps.re.round(), steps.im.round()) / EXPONENT_STEPS
+                        };
+                        let d = fractal.parameters().into_iter().find(|parameter| parameter.name == "d");
+                        let d = d.map(|d| stepped_exponent(d.value));
+                        match d.and_then(|d| Some((d, fractal.with_parameter("d", d)?))) {
+                            Some((d, stepped)) => {
+                                if in_julia {
+                                    if let Some(parent) = fractals[current].with_parameter("d", d) {
+                                        fractals[current] = parent;
+                                    }
+                                } else {
+                                    fractals[current] = Arc::clone(&stepped);
+                                }
+                                fractal = stepped;
+                                screen.invalidate()?;
+                                true
+                            }
+                            None => false,
+                        }
+                    }
+                    // fractals defined by text, like the newton polynomial, are edited in the status bar
+                    Key::Char('e') => match fractal.source() {
+                        Some(source) => {
+                            screen.prompt = Some(Prompt::new("Edit", source));
+                            true
+                        }
+                        None => false,
+                    },
+                    Key::Char('i') => {
+                        show_inset = !show_inset;
+                        true
+                    }
+                    // rotation turns every sample, so nothing in the buffer can be kept, it is only shown until replaced
+                    Key::Char('<') | Key::Char('>') => {
+                        let step = std::f64::consts::PI / 12.0;
+                        screen.viewport.rotation += if key == Key::Char('<') { step } else { -step };
+                        screen.invalidate()?;
+                        true
+                    }
+                    _ => false,
+                };
+                if should_end_program {
                     break;
                 }
-                _ => {}
             }
+            if redraw {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
         }
         if should_end_program {
             break;
@@ -1,99 +1,412 @@
 extern crate num;
 use num::complex::Complex;
-use std::sync::mpsc;
-use std::thread;
+use std::sync::Arc;
 
-use crate::terminal::{cyclic_buffer, screen};
-pub const IN_FRACTAL: char = '*';
-pub const OUTSIDE_FRACTAL: char = ' ';
-pub const MIN_ITER: i32 = 15;
+use crate::fixed::{Fixed, FixedComplex};
+use crate::fractal::{DefaultView, Escape, Fractal, Parameter, Series};
+use crate::julia::Julia;
+// a large escape radius makes the normalized iteration count continuous across bands
+pub const BAILOUT_RADIUS: f64 = 256.0;
+// how much smaller than the reference a perturbed orbit may get before it counts as a glitch
+const GLITCH_TOLERANCE: f64 = 1e-6;
+// relative error of the series against the exactly iterated probes, beyond which no more iterations are skipped
+const SERIES_TOLERANCE: f64 = 1e-9;
+// an f64 orbit that comes back this close to an earlier point has settled into a cycle. f64 samples are never
+// closer than 1e-12, so an orbit that only lingers near a cycle has to come within a thousandth of a sample of
+// it to be mistaken for one, while an orbit that really settled repeats down to the last few bits
+const CYCLE_TOLERANCE: f64 = 1e-15;
+pub const QUADRATIC: Complex<f64> = Complex::new(2.0, 0.0);
+// below this the sets stop being bounded in any useful sense, and 0^d is not defined for re(d) <= 0
+pub const MIN_EXPONENT: f64 = 1.1;
 
-struct PixelWithCoords {
-    coords: (u16, u16),
-    value: cyclic_buffer::Pixel,
+// z -> z^d + c, starting from z = 0. d = 2 is the mandelbrot set, anything else a multibrot
+pub struct Mandelbrot {
+    pub d: Complex<f64>,
 }
 
-pub fn render_whole_mandelbrot(
-    screen: &mut screen::Screen,
-    n_iter: u16,
-) -> Result<(), &'static str> {
-    let (w, h) = screen.term_size;
-    let mut coords_to_draw: Vec<(Complex<f64>, (u16, u16))> = Vec::new();
-    for x in 0..w {
-        for y in 0..h {
-            if let cyclic_buffer::Pixel::Recompute = screen.buffer.get(x, y)? {
-                coords_to_draw.push((screen.get_complex_coords(x, y)?, (x, y)));
-            }
+impl Fractal for Mandelbrot {
+    fn name(&self) -> String {
+        if self.d == QUADRATIC {
+            String::from("Mandelbrot")
+        } else {
+            String::from("Multibrot")
         }
     }
-
-    let n_threads = 10;
-    let chunk_size = (coords_to_draw.len() / n_threads) as usize;
-
-    let mut bunches = Vec::new();
-    let mut i = 0;
-    let mut j = 0;
-    while i < coords_to_draw.len() {
-        let mut bunch = Vec::new();
-        while j + i < coords_to_draw.len() && j < chunk_size {
-            bunch.push(coords_to_draw[j + i]);
-            j += 1;
-        }
-        i += bunch.len();
-        j = 0;
-        bunches.push(bunch);
-    }
-
-    let (tx, rx) = mpsc::channel::<PixelWithCoords>();
-
-    for coord_bunch in bunches {
-        let local_tx = tx.clone();
-        thread::spawn(move || {
-            for c in coord_bunch {
-                local_tx
-                    .send(PixelWithCoords {
-                        coords: c.1,
-                        value: cyclic_buffer::Pixel::Value(
-                            if compute_mandelbrot_pixel(c.0, n_iter) {
-                                IN_FRACTAL
-                            } else {
-                                OUTSIDE_FRACTAL
-                            },
-                        ),
-                    })
-                    .unwrap();
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter { name: "d", value: self.d }]
+    }
+    fn with_parameter(&self, name: &str, value: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        match name {
+            "d" if value.re >= MIN_EXPONENT => Some(Arc::new(Mandelbrot { d: value })),
+            _ => None,
+        }
+    }
+    fn julia(&self, c: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        Some(Arc::new(Julia { c, d: self.d }))
+    }
+    // the quadratic set sits left of the origin, the others are roughly centred on it
+    fn default_view(&self) -> DefaultView {
+        if self.d == QUADRATIC {
+            return DefaultView {
+                center: Complex::new(-0.5, 0.0),
+                radius: 1.25,
+            };
+        }
+        DefaultView {
+            center: Complex::new(0.0, 0.0),
+            radius: f64::min(0.75 * escape_radius(0.0, self.d), 4.0),
+        }
+    }
+    fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape {
+        compute_mandelbrot_pixel(c, self.d, n_iter)
+    }
+    // only the quadratic set has a fixed point path, other exponents need logarithms
+    fn supports_fixed(&self) -> bool {
+        self.d == QUADRATIC
+    }
+    fn compute_fixed(&self, c: &FixedComplex, n_iter: u16) -> Escape {
+        if self.d != QUADRATIC {
+            return self.compute(c.to_f64(), n_iter);
+        }
+        compute_mandelbrot_pixel_fixed(c, n_iter)
+    }
+    fn supports_perturbation(&self) -> bool {
+        self.d == QUADRATIC
+    }
+    // followed without the early exits of the pixels, every point perturbed from it needs the whole orbit
+    fn reference_orbit(&self, c: &FixedComplex, n_iter: u16) -> Vec<Complex<f64>> {
+        let limbs = c.fraction_limbs();
+        let (mut x, mut y) = (Fixed::zero(limbs), Fixed::zero(limbs));
+        let (mut x2, mut y2) = (Fixed::zero(limbs), Fixed::zero(limbs));
+        let mut orbit = vec![Complex::new(0.0, 0.0)];
+        for _ in 0..n_iter {
+            let xy = x.mul(&y);
+            x = x2.sub(&y2).add(&c.re);
+            y = xy.double().add(&c.im);
+            x2 = x.mul(&x);
+            y2 = y.mul(&y);
+            orbit.push(Complex::new(x.to_f64(), y.to_f64()));
+            if x2.to_f64() + y2.to_f64() > BAILOUT_RADIUS * BAILOUT_RADIUS {
+                break;
             }
-        });
+        }
+        orbit
     }
-
-    for _i in 0..coords_to_draw.len() {
-        let pixel = rx.recv();
-        match pixel {
-            Ok(px) => screen.putchar(px.coords.0, px.coords.1, px.value)?,
-            Err(_e) => {
-                return Err("could not recieve from reciever");
+    // the coefficients follow from substituting the series into dz -> 2 Z dz + dz^2 + dc. the fourth one is
+    // only kept to bound the error of leaving it out: near minibrots the orbit is sensitive enough that an
+    // error in dz far below a sample still moves the escape by hundreds of iterations, so the series stops once
+    // that term could be larger than the rounding f64 makes in dz anyway. every step the probes are iterated
+    // exactly as well, and the series also stops at the first step where it disagrees with them
+    fn series(&self, orbit: &[Complex<f64>], probes: &[Complex<f64>], n_iter: u16) -> Series {
+        let mut series = Series::NONE;
+        let [mut a, mut b, mut c] = series.coefficients;
+        let mut d = Complex::new(0.0, 0.0);
+        // the probes lie on the edges of the view, so no point is further from the reference
+        let radius = probes.iter().map(|dc| dc.norm()).fold(0.0, f64::max);
+        let mut deltas = vec![Complex::new(0.0, 0.0); probes.len()];
+        // the last step is left to the points, so that escaping is always decided by them
+        let limit = std::cmp::min(n_iter as usize, orbit.len()).saturating_sub(1);
+        for i in 0..limit {
+            let reference = orbit[i];
+            (a, b, c, d) = (
+                2.0 * reference * a + 1.0,
+                2.0 * reference * b + a * a,
+                2.0 * reference * c + 2.0 * a * b,
+                2.0 * reference * d + 2.0 * a * c + b * b,
+            );
+            // |d| r^4 against f64::EPSILON |a| r, coefficients that overflowed stop the series too
+            let truncation = d.norm() * radius.powi(3);
+            if !truncation.is_finite() || truncation > f64::EPSILON * a.norm() {
+                return series;
+            }
+            let candidate = Series {
+                skipped: i as u16 + 1,
+                coefficients: [a, b, c],
+            };
+            for (delta, dc) in deltas.iter_mut().zip(probes) {
+                *delta = 2.0 * reference * *delta + *delta * *delta + dc;
+                let z_norm = (orbit[i + 1] + *delta).norm_sqr();
+                // a probe that escapes or glitches here means its neighbours might too
+                if z_norm > BAILOUT_RADIUS * BAILOUT_RADIUS || z_norm < GLITCH_TOLERANCE * orbit[i + 1].norm_sqr() {
+                    return series;
+                }
+                if (candidate.delta(*dc) - *delta).norm() > SERIES_TOLERANCE * delta.norm() {
+                    return series;
+                }
             }
+            series = candidate;
         }
+        series
     }
+    fn compute_perturbed(&self, orbit: &[Complex<f64>], series: &Series, dc: Complex<f64>, n_iter: u16) -> Option<Escape> {
+        compute_mandelbrot_pixel_perturbed(orbit, series, dc, n_iter)
+    }
+}
+
+// z^d on the principal branch, with the argument of z in (-pi, pi].
+// the cut along the negative real axis is where non-integer exponents show seams
+pub fn power(z: Complex<f64>, d: Complex<f64>) -> Complex<f64> {
+    if d == QUADRATIC {
+        z * z
+    } else if d.im == 0.0 && d.re.fract() == 0.0 {
+        z.powi(d.re as i32)
+    } else if z.re == 0.0 && z.im == 0.0 {
+        z
+    } else if d.im == 0.0 {
+        z.powf(d.re)
+    } else {
+        z.powc(d)
+    }
+}
 
-    Ok(())
+// once |z| is past this, z^d + c is guaranteed to escape.
+// |z^d| >= |z|^re(d) * e^(-pi |im(d)|) on the principal branch, which has to outgrow |z| + |c|
+pub fn escape_radius(c_norm: f64, d: Complex<f64>) -> f64 {
+    let k = (2.0 * (std::f64::consts::PI * d.im.abs()).exp()).powf(1.0 / (d.re - 1.0));
+    f64::max(c_norm, k)
 }
 
-fn compute_mandelbrot_pixel(c: Complex<f64>, n_iter: u16) -> bool {
-    // returns true if the pixel is in the set
-    let mut in_set = true;
+// the bailout actually used, never below BAILOUT_RADIUS so smooth colouring stays continuous
+pub fn bailout_sqr(c: Complex<f64>, d: Complex<f64>) -> f64 {
+    let radius = f64::max(escape_radius(c.norm(), d), BAILOUT_RADIUS);
+    radius * radius
+}
+
+// the main cardioid and the period 2 bulb hold most of the interior of the quadratic set,
+// and both have exact tests that need no iterating at all
+fn main_component_period(c: Complex<f64>) -> Option<u16> {
+    let x = c.re - 0.25;
+    let y2 = c.im * c.im;
+    let q = x * x + y2;
+    if q * (q + x) <= 0.25 * y2 {
+        Some(1)
+    } else if (c.re + 1.0) * (c.re + 1.0) + y2 <= 1.0 / 16.0 {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// brent's cycle detection: the orbit is compared with a saved point that jumps ahead after 1, 2, 4, ... steps,
+// so once an orbit has settled, a cycle of any period is caught within a few times its length
+struct Cycle<T> {
+    saved: T,
+    steps: u16,
+    window: u16,
+    tolerance: f64,
+}
+
+impl<T: Copy> Cycle<T> {
+    fn new(z: T, tolerance: f64) -> Cycle<T> {
+        Cycle {
+            saved: z,
+            steps: 0,
+            window: 1,
+            tolerance,
+        }
+    }
+    // the period, once z has come back to within the tolerance of the saved point
+    fn step(&mut self, z: T, distance: impl Fn(&T, &T) -> f64) -> Option<u16> {
+        self.steps += 1;
+        if distance(&z, &self.saved) < self.tolerance {
+            return Some(self.steps);
+        }
+        if self.steps == self.window {
+            self.saved = z;
+            self.steps = 0;
+            self.window = self.window.saturating_mul(2);
+        }
+        None
+    }
+}
+
+fn compute_mandelbrot_pixel(c: Complex<f64>, d: Complex<f64>, n_iter: u16) -> Escape {
+    if d == QUADRATIC {
+        if let Some(period) = main_component_period(c) {
+            return Escape::periodic(n_iter, 0.0, period);
+        }
+    }
     let mut z = Complex::new(0.0, 0.0);
-    let mut z_norm;
+    let mut z_norm = 0.0;
+    let mut cycle = Cycle::new(z, CYCLE_TOLERANCE);
+    let bailout = bailout_sqr(c, d);
 
-    for _ in 0..n_iter {
-        z = z * z + c;
+    for i in 0..n_iter {
+        z = power(z, d) + c;
         z_norm = z.norm_sqr();
-        if z_norm > 4.0 {
-            in_set = false;
-            break;
-        } else if z_norm < 0.01 {
-            break;
+        if z_norm > bailout {
+            return Escape::escaped(i, z_norm, d.re);
+        }
+        if let Some(period) = cycle.step(z, |a, b| (a - b).l1_norm()) {
+            return Escape::periodic(n_iter, z_norm, period);
+        }
+    }
+    Escape::bounded(n_iter, z_norm)
+}
+
+// the quadratic iteration in fixed point, following compute_mandelbrot_pixel step for step
+fn compute_mandelbrot_pixel_fixed(c: &FixedComplex, n_iter: u16) -> Escape {
+    if let Some(period) = main_component_period(c.to_f64()) {
+        return Escape::periodic(n_iter, 0.0, period);
+    }
+    let limbs = c.fraction_limbs();
+    let (mut x, mut y) = (Fixed::zero(limbs), Fixed::zero(limbs));
+    let (mut x2, mut y2) = (Fixed::zero(limbs), Fixed::zero(limbs));
+    let mut z_norm = 0.0;
+    // the viewport picks the limbs with 64 bits to spare below the sample spacing, so 32 bits above the
+    // last limb is still far below a sample. the orbit is compared in fixed point, f64 could not resolve that
+    let mut cycle = Cycle::new(FixedComplex { re: x, im: y }, 2f64.powi(32 - 32 * limbs as i32));
+
+    for i in 0..n_iter {
+        let xy = x.mul(&y);
+        x = x2.sub(&y2).add(&c.re);
+        y = xy.double().add(&c.im);
+        x2 = x.mul(&x);
+        y2 = y.mul(&y);
+        // the magnitude only decides escaping, so f64 is precise enough for it
+        z_norm = x2.to_f64() + y2.to_f64();
+        if z_norm > BAILOUT_RADIUS * BAILOUT_RADIUS {
+            return Escape::escaped(i, z_norm, 2.0);
+        }
+        if let Some(period) = cycle.step(FixedComplex { re: x, im: y }, |a, b| a.sub(b).to_f64().l1_norm()) {
+            return Escape::periodic(n_iter, z_norm, period);
+        }
+    }
+    Escape::bounded(n_iter, z_norm)
+}
+
+// z = Z + dz for the reference orbit Z, where dz -> 2 Z dz + dz^2 + dc needs no more than f64.
+// the result follows compute_mandelbrot_pixel step for step, except that there is no cycle detection:
+// z itself is only known to f64, which cannot tell a cycle from an orbit lingering a sample away from one
+fn compute_mandelbrot_pixel_perturbed(orbit: &[Complex<f64>], series: &Series, dc: Complex<f64>, n_iter: u16) -> Option<Escape> {
+    // the first step of the reference is its c
+    if let Some(period) = orbit.get(1).and_then(|c| main_component_period(c + dc)) {
+        return Some(Escape::periodic(n_iter, 0.0, period));
+    }
+    let mut dz = series.delta(dc);
+    let mut z_norm = (orbit[series.skipped as usize] + dz).norm_sqr();
+
+    for i in series.skipped..n_iter {
+        // the reference escaped before this point did, its orbit says nothing about the rest
+        let reference = *orbit.get(i as usize + 1)?;
+        dz = 2.0 * orbit[i as usize] * dz + dz * dz + dc;
+        let z = reference + dz;
+        z_norm = z.norm_sqr();
+        if z_norm > BAILOUT_RADIUS * BAILOUT_RADIUS {
+            return Some(Escape::escaped(i, z_norm, 2.0));
+        }
+        // pauldelbrot's criterion: once z is much smaller than Z, dz has lost the precision that matters
+        if z_norm < GLITCH_TOLERANCE * reference.norm_sqr() {
+            return None;
+        }
+    }
+    Some(Escape::bounded(n_iter, z_norm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N_ITER: u16 = 2000;
+
+    // the iteration without any of the shortcuts, with the same bailout as the quadratic set
+    fn naive(c: Complex<f64>, n_iter: u16) -> u16 {
+        let mut z = Complex::new(0.0, 0.0);
+        for i in 0..n_iter {
+            z = z * z + c;
+            if z.norm_sqr() > BAILOUT_RADIUS * BAILOUT_RADIUS {
+                return i;
+            }
+        }
+        n_iter
+    }
+
+    fn mandelbrot(c: Complex<f64>) -> Escape {
+        compute_mandelbrot_pixel(c, QUADRATIC, N_ITER)
+    }
+
+    // n points around a curve given in polar coordinates, each pushed outwards by a factor of 1 + margin.
+    // the angles are offset by half a step so that none lands on the cusp of the cardioid
+    fn around(n: usize, radius: impl Fn(f64) -> f64, center: Complex<f64>, margin: f64) -> Vec<Complex<f64>> {
+        (0..n)
+            .map(|k| {
+                let angle = 2.0 * std::f64::consts::PI * (k as f64 + 0.5) / n as f64;
+                center + Complex::from_polar(radius(angle) * (1.0 + margin), angle)
+            })
+            .collect()
+    }
+
+    // the main cardioid in polar coordinates around its cusp at 1/4
+    fn cardioid(margin: f64) -> Vec<Complex<f64>> {
+        around(720, |angle| 0.5 * (1.0 - angle.cos()), Complex::new(0.25, 0.0), margin)
+    }
+
+    fn bulb(margin: f64) -> Vec<Complex<f64>> {
+        around(720, |_| 0.25, Complex::new(-1.0, 0.0), margin)
+    }
+
+    #[test]
+    fn the_cardioid_and_bulb_are_recognised_with_their_periods() {
+        for c in cardioid(-1e-3) {
+            assert_eq!(main_component_period(c), Some(1), "{}", c);
+        }
+        for c in bulb(-1e-3) {
+            assert_eq!(main_component_period(c), Some(2), "{}", c);
+        }
+        for (c, period) in [(Complex::new(0.0, 0.0), 1), (Complex::new(-0.5, 0.3), 1), (Complex::new(-1.0, 0.0), 2)] {
+            let escape = mandelbrot(c);
+            assert!(escape.interior);
+            assert_eq!((escape.iterations, escape.period), (N_ITER, Some(period)));
+        }
+    }
+
+    #[test]
+    fn other_components_are_caught_by_cycle_detection() {
+        // the centers of the period 3 bulb and the period 4 bulb on the real axis
+        for (c, period) in [(Complex::new(-0.122561, 0.744862), 3), (Complex::new(-1.310703, 0.0), 4)] {
+            assert_eq!(main_component_period(c), None);
+            let escape = mandelbrot(c);
+            assert!(escape.interior);
+            assert_eq!((escape.iterations, escape.period), (N_ITER, Some(period)));
+            let fixed = compute_mandelbrot_pixel_fixed(&FixedComplex::from_f64(c, 4), N_ITER);
+            assert_eq!((fixed.iterations, fixed.period), (N_ITER, Some(period)));
+        }
+    }
+
+    #[test]
+    fn points_just_outside_escape_like_the_naive_iteration() {
+        for margin in [1e-2, 1e-3, 1e-4] {
+            let mut escaped = 0;
+            let outside = cardioid(margin).into_iter().map(|c| (c, 1)).chain(bulb(margin).into_iter().map(|c| (c, 2)));
+            for (c, component) in outside {
+                // a point pushed out of the cardioid where it touches the bulb lands in the bulb
+                assert_ne!(main_component_period(c), Some(component), "{}", c);
+                let expected = naive(c, N_ITER);
+                let escape = mandelbrot(c);
+                if expected < N_ITER {
+                    escaped += 1;
+                    assert_eq!(escape.iterations, expected, "{}", c);
+                    assert!(!escape.interior);
+                    assert_eq!(escape.period, None);
+                } else {
+                    // the rest lie in the bulbs attached to the boundary, which no shortcut may let escape
+                    assert!(escape.interior, "{}", c);
+                }
+            }
+            assert!(escaped > 100, "only {} points escaped at margin {}", escaped, margin);
+        }
+    }
+
+    #[test]
+    fn points_just_outside_escape_like_the_naive_iteration_in_fixed_point() {
+        for c in cardioid(1e-4).into_iter().chain(bulb(1e-4)).step_by(7) {
+            let fixed = compute_mandelbrot_pixel_fixed(&FixedComplex::from_f64(c, 4), N_ITER);
+            let expected = naive(c, N_ITER);
+            if expected < N_ITER {
+                // fixed point rounds differently, which only shows on orbits that take long to escape
+                assert!((fixed.iterations as i32 - expected as i32).abs() <= 1, "{}", c);
+                assert_eq!(fixed.period, None);
+            }
         }
     }
-    return in_set;
 }
@@ -0,0 +1,417 @@
+extern crate num;
+use num::complex::Complex;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::fractal::{DefaultView, Escape, Fractal};
+
+// a step shorter than this counts as converged, and roots closer than it are the same root
+const TOLERANCE: f64 = 1e-6;
+const ROOT_ITERATIONS: usize = 500;
+
+// coefficients from the constant term up
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    pub coefficients: Vec<Complex<f64>>,
+}
+
+impl Polynomial {
+    // the polynomial with exactly these roots and leading coefficient 1
+    pub fn from_roots(roots: &[Complex<f64>]) -> Polynomial {
+        let mut coefficients = vec![Complex::new(1.0, 0.0)];
+        for root in roots {
+            // multiplies by (z - root)
+            let mut next = vec![Complex::new(0.0, 0.0); coefficients.len() + 1];
+            for (power, coefficient) in coefficients.iter().enumerate() {
+                next[power + 1] += coefficient;
+                next[power] -= coefficient * root;
+            }
+            coefficients = next;
+        }
+        Polynomial { coefficients }
+    }
+    pub fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+    // the value and the derivative at z, by horner's method
+    pub fn eval(&self, z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        let mut value = Complex::new(0.0, 0.0);
+        let mut derivative = Complex::new(0.0, 0.0);
+        for coefficient in self.coefficients.iter().rev() {
+            derivative = derivative * z + value;
+            value = value * z + coefficient;
+        }
+        (value, derivative)
+    }
+    // all roots at once with the durand-kerner method, repeated roots come out as close pairs.
+    // None if they did not converge to finite values, which happens once huge coefficients overflow the iteration
+    pub fn roots(&self) -> Option<Vec<Complex<f64>>> {
+        let degree = self.degree();
+        if degree == 0 {
+            return Some(Vec::new());
+        }
+        let leading = self.coefficients[degree];
+        // fdiv scales first, so that tiny or huge leading coefficients do not underflow or overflow the division
+        let monic = Polynomial {
+            coefficients: self.coefficients.iter().map(|c| c.fdiv(leading)).collect(),
+        };
+        // powers of a number that is neither real nor a root of unity, so no two guesses start symmetric
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<Complex<f64>> = (0..degree).map(|k| seed.powi(k as i32)).collect();
+        for _ in 0..ROOT_ITERATIONS {
+            let mut largest_step: f64 = 0.0;
+            for i in 0..degree {
+                let mut denominator = Complex::new(1.0, 0.0);
+                for j in 0..degree {
+                    if i != j {
+                        denominator *= roots[i] - roots[j];
+                    }
+                }
+                if denominator.norm_sqr() == 0.0 {
+                    continue;
+                }
+                let step = monic.eval(roots[i]).0 / denominator;
+                roots[i] -= step;
+                largest_step = largest_step.max(step.norm());
+            }
+            if largest_step < TOLERANCE * TOLERANCE {
+                return Some(roots).filter(|roots| roots.iter().all(|root| root.is_finite()));
+            }
+        }
+        None
+    }
+}
+
+// newton's method on a polynomial, every point is coloured by the root it converges to
+pub struct Newton {
+    source: String,
+    polynomial: Polynomial,
+    roots: Vec<Complex<f64>>,
+}
+
+impl Newton {
+    // source is either coefficients from the highest power down, like "1 0 0 -1" for z^3 - 1,
+    // or "roots" followed by the roots, like "roots 1 -0.5+0.866i -0.5-0.866i"
+    pub fn parse(source: &str) -> Result<Newton> {
+        let mut tokens = tokenize(source);
+        let by_roots = matches!(tokens.first(), Some((_, word)) if *word == "roots");
+        if by_roots {
+            tokens.remove(0);
+        }
+        let first_column = tokens.first().map_or(1, |(column, _)| *column);
+        let numbers = tokens
+            .iter()
+            .map(|(column, token)| parse_complex(token, *column))
+            .collect::<Result<Vec<Complex<f64>>>>()?;
+        // inf, nan and numbers past the range of f64 parse, but no root or view can be made of them
+        if let Some(((column, token), _)) = tokens.iter().zip(&numbers).find(|(_, number)| !number.is_finite()) {
+            return Err(parse_error(*column, &format!("'{}' is not a finite number", token)));
+        }
+        let (polynomial, roots) = if by_roots {
+            if numbers.is_empty() {
+                return Err(parse_error(source.chars().count() + 1, "expected at least one root"));
+            }
+            (Polynomial::from_roots(&numbers), numbers)
+        } else {
+            // compared with zero directly, the norm of a tiny coefficient would underflow to it
+            let leading = numbers.iter().position(|c| c.re != 0.0 || c.im != 0.0);
+            let coefficients: Vec<Complex<f64>> = match leading {
+                Some(leading) => numbers[leading..].iter().rev().cloned().collect(),
+                None => Vec::new(),
+            };
+            if coefficients.len() < 2 {
+                return Err(parse_error(first_column, "the polynomial needs a degree of at least 1"));
+            }
+            let polynomial = Polynomial { coefficients };
+            let roots = polynomial
+                .roots()
+                .ok_or_else(|| parse_error(first_column, "the roots of this polynomial cannot be found"))?;
+            (polynomial, roots)
+        };
+        let newton = Newton {
+            source: String::from(source.trim()),
+            polynomial,
+            roots,
+        };
+        if !newton.polynomial.coefficients.iter().all(|c| c.is_finite()) {
+            return Err(parse_error(first_column, "the coefficients of this polynomial are too large"));
+        }
+        let view = newton.default_view();
+        if !(view.center.is_finite() && view.radius.is_finite()) {
+            return Err(parse_error(first_column, "the roots are too far apart to show"));
+        }
+        Ok(newton)
+    }
+    pub fn roots(&self) -> &[Complex<f64>] {
+        &self.roots
+    }
+}
+
+impl Fractal for Newton {
+    fn name(&self) -> String {
+        format!("Newton, degree {}", self.polynomial.degree())
+    }
+    // all the roots with some room around them
+    fn default_view(&self) -> DefaultView {
+        let center = self.roots.iter().sum::<Complex<f64>>() / self.roots.len().max(1) as f64;
+        let spread = self.roots.iter().map(|root| (root - center).norm()).fold(0.0, f64::max);
+        DefaultView {
+            center,
+            radius: 1.5 * spread + 0.5,
+        }
+    }
+    // convergence does not get slower deeper in, so the budget stays the same at every scale
+    fn default_iterations(&self, _scale: f64) -> i32 {
+        64
+    }
+    fn describe(&self) -> Vec<String> {
+        let mut roots = String::from("Roots:");
+        for (index, root) in self.roots.iter().enumerate() {
+            roots.push_str(&format!(" [{}] {:.3}{:+.3}i", index + 1, root.re, root.im));
+        }
+        vec![format!("Polynomial: {}", self.source), roots]
+    }
+    fn source(&self) -> Option<String> {
+        Some(self.source.clone())
+    }
+    fn edit(&self, source: &str) -> Result<Arc<dyn Fractal>> {
+        Ok(Arc::new(Newton::parse(source)?))
+    }
+    fn compute(&self, z: Complex<f64>, n_iter: u16) -> Escape {
+        let mut z = z;
+        for i in 0..n_iter {
+            let (value, derivative) = self.polynomial.eval(z);
+            if derivative.norm_sqr() == 0.0 {
+                break;
+            }
+            z -= value / derivative;
+            for (index, root) in self.roots.iter().enumerate() {
+                let distance_sqr = (z - root).norm_sqr();
+                if distance_sqr < TOLERANCE * TOLERANCE {
+                    // the distance roughly squares every step near a simple root, which gives the fraction
+                    let fraction = (distance_sqr.ln() / (TOLERANCE * TOLERANCE).ln()).log2().clamp(0.0, 1.0);
+                    return Escape {
+                        iterations: i,
+                        smooth: (i as f64 + 1.0 - fraction).max(0.0),
+                        norm_sqr: distance_sqr,
+                        interior: false,
+                        basin: Some(index as u16),
+                        period: None,
+                    };
+                }
+            }
+        }
+        // never settled on a root, these points make up the julia set of the newton map
+        Escape::bounded(n_iter, z.norm_sqr())
+    }
+}
+
+fn parse_error(column: usize, message: &str) -> Error {
+    Error::Parse {
+        column,
+        message: String::from(message),
+    }
+}
+
+// words separated by whitespace or commas, with the column each one starts at
+fn tokenize(source: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, (offset, c)) in source.char_indices().enumerate() {
+        let separator = c.is_whitespace() || c == ',';
+        match start {
+            None if !separator => start = Some((index + 1, offset)),
+            Some((column, begin)) if separator => {
+                tokens.push((column, &source[begin..offset]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((column, begin)) = start {
+        tokens.push((column, &source[begin..]));
+    }
+    tokens
+}
+
+// numbers like 2, -0.5, 3i, -i or 1.5-2i
+fn parse_complex(token: &str, column: usize) -> Result<Complex<f64>> {
+    let invalid = || parse_error(column, &format!("'{}' is not a number", token));
+    if let Ok(re) = token.parse::<f64>() {
+        return Ok(Complex::new(re, 0.0));
+    }
+    let body = token.strip_suffix('i').ok_or_else(invalid)?;
+    // the sign that starts the imaginary part, skipping a leading sign and exponents like 1e-3
+    let bytes = body.as_bytes();
+    let split = (1..bytes.len())
+        .rev()
+        .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-') && bytes[i - 1] != b'e' && bytes[i - 1] != b'E');
+    let (re, im) = match split {
+        Some(split) => (body[..split].parse::<f64>().map_err(|_| invalid())?, &body[split..]),
+        None => (0.0, body),
+    };
+    let im = match im {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        im => im.parse::<f64>().map_err(|_| invalid())?,
+    };
+    Ok(Complex::new(re, im))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(re: f64, im: f64) -> Complex<f64> {
+        Complex::new(re, im)
+    }
+
+    fn error<T>(result: Result<T>) -> (usize, String) {
+        match result {
+            Err(Error::Parse { column, message }) => (column, message),
+            Err(other) => panic!("expected a parse error, got {}", other),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    fn parse_error_of(source: &str) -> (usize, String) {
+        error(Newton::parse(source))
+    }
+
+    // every root of the polynomial is close to one of the expected ones and the other way round
+    fn assert_same_roots(found: &[Complex<f64>], expected: &[Complex<f64>]) {
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for root in expected {
+            assert!(found.iter().any(|found| (found - root).norm() < 1e-6), "{} is missing from {:?}", root, found);
+        }
+        for root in found {
+            assert!(expected.iter().any(|expected| (expected - root).norm() < 1e-6), "{} is not a root", root);
+        }
+    }
+
+    #[test]
+    fn tokens_are_split_on_whitespace_and_commas() {
+        assert_eq!(tokenize("1 0  -1"), vec![(1, "1"), (3, "0"), (6, "-1")]);
+        assert_eq!(tokenize("roots 1,-1, 2i"), vec![(1, "roots"), (7, "1"), (9, "-1"), (13, "2i")]);
+        assert_eq!(tokenize("  \t"), vec![]);
+        // columns count characters, not bytes
+        assert_eq!(tokenize("1\u{a0}2"), vec![(1, "1"), (3, "2")]);
+    }
+
+    #[test]
+    fn complex_numbers_in_every_form() {
+        assert_eq!(parse_complex("2", 1).unwrap(), c(2.0, 0.0));
+        assert_eq!(parse_complex("-0.5", 1).unwrap(), c(-0.5, 0.0));
+        assert_eq!(parse_complex("3i", 1).unwrap(), c(0.0, 3.0));
+        assert_eq!(parse_complex("i", 1).unwrap(), c(0.0, 1.0));
+        assert_eq!(parse_complex("-i", 1).unwrap(), c(0.0, -1.0));
+        assert_eq!(parse_complex("1.5-2i", 1).unwrap(), c(1.5, -2.0));
+        assert_eq!(parse_complex("1+i", 1).unwrap(), c(1.0, 1.0));
+        assert_eq!(parse_complex("1e-3-2E+2i", 1).unwrap(), c(1e-3, -200.0));
+        assert_eq!(error(parse_complex("1.5x", 4)), (4, String::from("'1.5x' is not a number")));
+        assert_eq!(error(parse_complex("1+-2i", 4)), (4, String::from("'1+-2i' is not a number")));
+    }
+
+    #[test]
+    fn polynomials_from_roots_expand_the_product() {
+        // (z - 1)(z + 1) = z^2 - 1
+        let polynomial = Polynomial::from_roots(&[c(1.0, 0.0), c(-1.0, 0.0)]);
+        assert_eq!(polynomial.coefficients, vec![c(-1.0, 0.0), c(0.0, 0.0), c(1.0, 0.0)]);
+        // (z - i)(z + i) = z^2 + 1
+        let polynomial = Polynomial::from_roots(&[c(0.0, 1.0), c(0.0, -1.0)]);
+        assert_eq!(polynomial.coefficients, vec![c(1.0, 0.0), c(0.0, 0.0), c(1.0, 0.0)]);
+        assert_eq!(Polynomial::from_roots(&[]).degree(), 0);
+    }
+
+    #[test]
+    fn evaluation_gives_the_value_and_the_derivative() {
+        // z^3 - 1 at 2: 7, and its derivative 3 z^2: 12
+        let polynomial = Polynomial::from_roots(&[c(1.0, 0.0), c(-0.5, 0.75f64.sqrt()), c(-0.5, -(0.75f64.sqrt()))]);
+        let (value, derivative) = polynomial.eval(c(2.0, 0.0));
+        assert!((value - c(7.0, 0.0)).norm() < 1e-12);
+        assert!((derivative - c(12.0, 0.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn the_root_finder_recovers_the_roots() {
+        for roots in [
+            vec![c(1.0, 0.0), c(-1.0, 0.0), c(0.5, 2.0)],
+            vec![c(0.0, 0.0), c(3.0, -4.0)],
+            vec![c(-2.0, 1.0), c(1.0, 1.0), c(0.0, -1.0), c(2.5, 0.0), c(-0.5, -0.5)],
+        ] {
+            assert_same_roots(&Polynomial::from_roots(&roots).roots().unwrap(), &roots);
+        }
+        // a scaled polynomial has the same roots
+        let polynomial = Polynomial {
+            coefficients: vec![c(-2.0, 0.0), c(0.0, 0.0), c(2.0, 0.0)],
+        };
+        assert_same_roots(&polynomial.roots().unwrap(), &[c(1.0, 0.0), c(-1.0, 0.0)]);
+    }
+
+    #[test]
+    fn repeated_roots_come_out_as_close_pairs() {
+        let roots = Polynomial::from_roots(&[c(1.0, 0.0), c(1.0, 0.0), c(-2.0, 0.0)]).roots().unwrap();
+        assert_eq!(roots.iter().filter(|root| (*root - c(1.0, 0.0)).norm() < 1e-4).count(), 2);
+    }
+
+    #[test]
+    fn coefficients_are_given_from_the_highest_power() {
+        let newton = Newton::parse("1 0 0 -1").unwrap();
+        assert_eq!(newton.polynomial.coefficients, vec![c(-1.0, 0.0), c(0.0, 0.0), c(0.0, 0.0), c(1.0, 0.0)]);
+        let third = 0.75f64.sqrt();
+        assert_same_roots(newton.roots(), &[c(1.0, 0.0), c(-0.5, third), c(-0.5, -third)]);
+        // leading zeros do not count towards the degree, however small the first nonzero coefficient is
+        assert_eq!(Newton::parse("0 0 1 -1").unwrap().polynomial.degree(), 1);
+        assert_eq!(Newton::parse("1e-200 1").unwrap().polynomial.degree(), 1);
+    }
+
+    #[test]
+    fn roots_are_kept_as_given() {
+        let newton = Newton::parse("roots 1 -1 0.5+2i").unwrap();
+        assert_eq!(newton.roots(), &[c(1.0, 0.0), c(-1.0, 0.0), c(0.5, 2.0)]);
+        assert_eq!(newton.polynomial.degree(), 3);
+    }
+
+    #[test]
+    fn mistakes_are_reported_at_their_column() {
+        assert_eq!(parse_error_of("1 0 x -1"), (5, String::from("'x' is not a number")));
+        assert_eq!(parse_error_of("roots"), (6, String::from("expected at least one root")));
+        assert_eq!(parse_error_of("  0 0 5"), (3, String::from("the polynomial needs a degree of at least 1")));
+        assert_eq!(parse_error_of(""), (1, String::from("the polynomial needs a degree of at least 1")));
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected_at_their_column() {
+        assert_eq!(parse_error_of("inf 1"), (1, String::from("'inf' is not a finite number")));
+        assert_eq!(parse_error_of("1 nan"), (3, String::from("'nan' is not a finite number")));
+        assert_eq!(parse_error_of("1 1e400"), (3, String::from("'1e400' is not a finite number")));
+        assert_eq!(parse_error_of("roots nan"), (7, String::from("'nan' is not a finite number")));
+        assert_eq!(parse_error_of("roots 1 inf"), (9, String::from("'inf' is not a finite number")));
+        assert_eq!(parse_error_of("1 1+infi"), (3, String::from("'1+infi' is not a finite number")));
+    }
+
+    #[test]
+    fn polynomials_without_usable_roots_are_rejected() {
+        // the iteration overflows long before it gets near roots of 1e150
+        assert_eq!(parse_error_of("1 0 1e300"), (1, String::from("the roots of this polynomial cannot be found")));
+        assert_eq!(parse_error_of("roots 1e200 -1e200"), (7, String::from("the coefficients of this polynomial are too large")));
+        assert_eq!(parse_error_of("roots 1e308 1e308 1"), (7, String::from("the coefficients of this polynomial are too large")));
+    }
+
+    #[test]
+    fn accepted_polynomials_have_a_finite_view() {
+        for source in ["1 0 0 -1", "roots 1 -1 0.5+2i", "1 1e300", "1e308 1", "2i 0 1"] {
+            let view = Newton::parse(source).unwrap().default_view();
+            assert!(view.center.is_finite() && view.radius.is_finite(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn points_converge_to_the_nearest_root() {
+        let newton = Newton::parse("roots 1 -1").unwrap();
+        assert_eq!(newton.compute(c(0.9, 0.1), 64).basin, Some(0));
+        assert_eq!(newton.compute(c(-3.0, 0.5), 64).basin, Some(1));
+        // the imaginary axis is the julia set of the newton map of z^2 - 1, it never picks a root
+        assert_eq!(newton.compute(c(0.0, 0.7), 64).basin, None);
+    }
+}
@@ -0,0 +1,217 @@
+use std::env;
+
+use crate::fractal::Escape;
+
+// steps of its fractional part never repeat, so neighbouring basin indices get distant colours
+const GOLDEN_RATIO: f64 = 1.618_033_988_749_895;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+// how many colours the terminal can show, picked once from the environment
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    Monochrome,
+}
+
+impl ColorDepth {
+    pub fn detect() -> ColorDepth {
+        ColorDepth::from_env(env::var("COLORTERM").ok(), env::var("TERM").ok())
+    }
+    pub fn from_env(colorterm: Option<String>, term: Option<String>) -> ColorDepth {
+        if let Some(colorterm) = colorterm {
+            let colorterm = colorterm.to_lowercase();
+            if colorterm == "truecolor" || colorterm == "24bit" {
+                return ColorDepth::TrueColor;
+            }
+        }
+        match term {
+            None => ColorDepth::Monochrome,
+            Some(term) if term.is_empty() || term == "dumb" => ColorDepth::Monochrome,
+            Some(term) if term.contains("truecolor") || term.contains("direct") => {
+                ColorDepth::TrueColor
+            }
+            Some(term) if term.contains("256") => ColorDepth::Ansi256,
+            Some(_) => ColorDepth::Ansi16,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorDepth::TrueColor => "24-bit",
+            ColorDepth::Ansi256 => "256",
+            ColorDepth::Ansi16 => "16",
+            ColorDepth::Monochrome => "mono",
+        }
+    }
+    // escape sequence that sets the background colour of the following cells
+    pub fn background(self, color: Rgb) -> String {
+        match self {
+            ColorDepth::TrueColor => format!("\x1b[48;2;{};{};{}m", color.0, color.1, color.2),
+            ColorDepth::Ansi256 => format!("\x1b[48;5;{}m", to_ansi256(color)),
+            ColorDepth::Ansi16 => {
+                let index = to_ansi16(color);
+                if index < 8 {
+                    format!("\x1b[{}m", 40 + index)
+                } else {
+                    format!("\x1b[{}m", 100 + index - 8)
+                }
+            }
+            ColorDepth::Monochrome => String::new(),
+        }
+    }
+    // escape sequence that sets the foreground colour of the following cells
+    pub fn foreground(self, color: Rgb) -> String {
+        match self {
+            ColorDepth::TrueColor => format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2),
+            ColorDepth::Ansi256 => format!("\x1b[38;5;{}m", to_ansi256(color)),
+            ColorDepth::Ansi16 => {
+                let index = to_ansi16(color);
+                if index < 8 {
+                    format!("\x1b[{}m", 30 + index)
+                } else {
+                    format!("\x1b[{}m", 90 + index - 8)
+                }
+            }
+            ColorDepth::Monochrome => String::new(),
+        }
+    }
+}
+
+// the xterm 6x6x6 colour cube, the grey ramp is not used
+fn to_ansi256(color: Rgb) -> u8 {
+    let level = |v: u8| ((v as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(color.0) + 6 * level(color.1) + level(color.2)
+}
+
+const ANSI16: [Rgb; 16] = [
+    Rgb(0, 0, 0),
+    Rgb(205, 0, 0),
+    Rgb(0, 205, 0),
+    Rgb(205, 205, 0),
+    Rgb(0, 0, 238),
+    Rgb(205, 0, 205),
+    Rgb(0, 205, 205),
+    Rgb(229, 229, 229),
+    Rgb(127, 127, 127),
+    Rgb(255, 0, 0),
+    Rgb(0, 255, 0),
+    Rgb(255, 255, 0),
+    Rgb(92, 92, 255),
+    Rgb(255, 0, 255),
+    Rgb(0, 255, 255),
+    Rgb(255, 255, 255),
+];
+
+fn to_ansi16(color: Rgb) -> u8 {
+    let distance = |other: &Rgb| {
+        let dr = color.0 as i32 - other.0 as i32;
+        let dg = color.1 as i32 - other.1 as i32;
+        let db = color.2 as i32 - other.2 as i32;
+        dr * dr + dg * dg + db * db
+    };
+    let mut best = 0;
+    for (index, candidate) in ANSI16.iter().enumerate() {
+        if distance(candidate) < distance(&ANSI16[best]) {
+            best = index;
+        }
+    }
+    best as u8
+}
+
+// a named, cyclic gradient that escape iterations are mapped onto
+pub struct Palette {
+    pub name: &'static str,
+    stops: &'static [Rgb],
+    period: f64, // how many iterations it takes to go through the whole gradient once
+    interior: Rgb,
+}
+
+pub const PALETTES: [Palette; 5] = [
+    Palette {
+        name: "none",
+        stops: &[],
+        period: 1.0,
+        interior: Rgb(0, 0, 0),
+    },
+    Palette {
+        name: "classic",
+        stops: &[
+            Rgb(0, 7, 100),
+            Rgb(32, 107, 203),
+            Rgb(237, 255, 255),
+            Rgb(255, 170, 0),
+            Rgb(0, 2, 0),
+        ],
+        period: 48.0,
+        interior: Rgb(0, 0, 0),
+    },
+    Palette {
+        name: "fire",
+        stops: &[
+            Rgb(20, 0, 0),
+            Rgb(180, 20, 0),
+            Rgb(255, 140, 0),
+            Rgb(255, 240, 120),
+            Rgb(120, 10, 0),
+        ],
+        period: 32.0,
+        interior: Rgb(0, 0, 0),
+    },
+    Palette {
+        name: "ocean",
+        stops: &[
+            Rgb(0, 20, 40),
+            Rgb(0, 90, 140),
+            Rgb(40, 200, 200),
+            Rgb(220, 250, 240),
+            Rgb(0, 60, 100),
+        ],
+        period: 40.0,
+        interior: Rgb(0, 0, 0),
+    },
+    Palette {
+        name: "grayscale",
+        stops: &[Rgb(0, 0, 0), Rgb(255, 255, 255)],
+        period: 24.0,
+        interior: Rgb(0, 0, 0),
+    },
+];
+
+impl Palette {
+    pub fn index_of(name: &str) -> usize {
+        PALETTES.iter().position(|p| p.name == name).unwrap_or(0)
+    }
+    // returns None if this palette does not colour anything
+    pub fn color(&self, escape: &Escape, smooth: bool) -> Option<Rgb> {
+        if self.stops.is_empty() {
+            return None;
+        }
+        if escape.interior {
+            return Some(self.interior);
+        }
+        // basins are told apart by spreading them over the gradient, and darken with the steps they took
+        if let Some(basin) = escape.basin {
+            let Rgb(r, g, b) = self.at(self.period * basin as f64 * GOLDEN_RATIO.fract());
+            let light = f64::max(0.25, 1.0 - 0.06 * escape.iteration(smooth));
+            let dim = |c: u8| (c as f64 * light).round() as u8;
+            return Some(Rgb(dim(r), dim(g), dim(b)));
+        }
+        Some(self.at(escape.iteration(smooth)))
+    }
+    // colour at a (possibly fractional) iteration count, wrapping around the gradient
+    pub fn at(&self, iteration: f64) -> Rgb {
+        if self.stops.len() == 1 {
+            return self.stops[0];
+        }
+        let t = (iteration / self.period).rem_euclid(1.0) * self.stops.len() as f64;
+        let index = t.floor() as usize % self.stops.len();
+        let next = (index + 1) % self.stops.len();
+        let frac = t - t.floor();
+        let lerp = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * frac).round() as u8;
+        let (a, b) = (self.stops[index], self.stops[next]);
+        Rgb(lerp(a.0, b.0), lerp(a.1, b.1), lerp(a.2, b.2))
+    }
+}
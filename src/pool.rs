@@ -0,0 +1,106 @@
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+// overrides the number of worker threads, which is otherwise one per available core
+pub const THREADS_VARIABLE: &str = "TERM_FRACTALS_THREADS";
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+thread_local! {
+    // true on the pool's own threads, whose panics are caught and never end the program
+    static WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+// worker threads that live as long as the program and take jobs from one shared queue,
+// so a thread that finishes a cheap job early simply picks up the next one
+pub struct Pool {
+    queue: Sender<Job>,
+    threads: usize,
+}
+
+impl Pool {
+    pub fn new(threads: usize) -> Pool {
+        let threads = threads.max(1);
+        let (queue, jobs) = mpsc::channel::<Job>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        for index in 0..threads {
+            let jobs = Arc::clone(&jobs);
+            // a worker that cannot be started just leaves the others more to do
+            let _ = thread::Builder::new()
+                .name(format!("render worker {}", index))
+                .spawn(move || work(&jobs));
+        }
+        Pool { queue, threads }
+    }
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // the workers only stop once the pool is dropped, so the queue is always open here
+        let _ = self.queue.send(Box::new(job));
+    }
+}
+
+fn work(jobs: &Mutex<Receiver<Job>>) {
+    WORKER.with(|worker| worker.set(true));
+    loop {
+        // the lock is only held while waiting, never while a job runs
+        let job = jobs.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recv();
+        match job {
+            // a panicking job loses its own results, but the worker stays around for the next one
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+// true if the calling thread is a worker of some pool
+pub fn is_worker() -> bool {
+    WORKER.with(|worker| worker.get())
+}
+
+pub fn default_threads() -> usize {
+    std::env::var(THREADS_VARIABLE)
+        .ok()
+        .and_then(|threads| threads.trim().parse::<usize>().ok())
+        .filter(|threads| *threads > 0)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(4, |threads| threads.get()))
+}
+
+static GLOBAL: OnceLock<Pool> = OnceLock::new();
+
+// the pool all renders share, sized by default_threads when the first render starts it
+pub fn global() -> &'static Pool {
+    GLOBAL.get_or_init(|| Pool::new(default_threads()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_know_they_run_on_a_worker() {
+        let pool = Pool::new(1);
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(is_worker()).unwrap());
+        assert!(rx.recv().unwrap());
+        assert!(!is_worker());
+    }
+
+    #[test]
+    fn a_panicking_job_leaves_the_worker_running() {
+        let pool = Pool::new(1);
+        pool.execute(|| panic!("a job that fails"));
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(()).unwrap());
+        assert!(rx.recv().is_ok());
+    }
+}
@@ -0,0 +1,757 @@
+extern crate num;
+use num::complex::Complex;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crate::error::{Error, Result};
+use crate::fixed::FixedComplex;
+use crate::fractal::{Escape, Fractal, Series};
+use crate::pool;
+use crate::stat_bar;
+use crate::terminal::render_mode::RenderMode;
+use crate::terminal::{cyclic_buffer, screen};
+use crate::viewport::{Precision, Viewport};
+
+// where a sample is, in whichever precision the render needs
+#[derive(Clone)]
+enum Point {
+    Double(Complex<f64>),
+    Fixed(Box<FixedComplex>),
+    Delta(Arc<Perturbed>, Complex<f64>), // dc away from the start of a reference orbit
+}
+
+// a reference orbit together with the iterations that all points of the view can skip along it
+struct Perturbed {
+    orbit: Arc<Vec<Complex<f64>>>,
+    series: Series,
+}
+
+struct PixelWithCoords {
+    coords: (u16, u16),
+    value: Option<Escape>, // None for a perturbed point that glitched
+}
+
+// references tried for the glitched points of one render, before falling back to fixed point for each of them
+const MAX_REFERENCES: usize = 8;
+// how far the center may move from the reference orbit, in screen sizes, before a new one is computed
+const REFERENCE_REACH: f64 = 2.0;
+
+// a reference orbit for perturbation, kept between frames so pans and zooms nearby can reuse it
+#[derive(Clone)]
+struct Reference {
+    fractal: Arc<dyn Fractal>,
+    c: FixedComplex,
+    n_iter: u16,
+    orbit: Arc<Vec<Complex<f64>>>,
+}
+
+impl Reference {
+    fn new(fractal: &Arc<dyn Fractal>, c: FixedComplex, n_iter: u16) -> Reference {
+        Reference {
+            fractal: Arc::clone(fractal),
+            c,
+            n_iter,
+            orbit: Arc::new(fractal.reference_orbit(&c, n_iter)),
+        }
+    }
+}
+
+// what perturbation rendering keeps from one frame to the next
+#[derive(Clone, Default)]
+pub struct PerturbationState {
+    reference: Option<Reference>,
+    pub references_used: usize, // in the last render, 0 if it did not use perturbation
+    pub skipped: u16,           // iterations the series approximation skipped for the main reference
+    pub finished: bool,         // false while a render that will replace these numbers is running
+}
+
+// a render is abandoned by moving the shared generation past the one it was started for.
+// workers look at it before every sample, so a cancelled render stops within one sample per thread
+#[derive(Clone)]
+struct Cancel {
+    generation: Arc<AtomicU64>,
+    started: u64,
+}
+
+impl Cancel {
+    fn never() -> Cancel {
+        Cancel {
+            generation: Arc::new(AtomicU64::new(0)),
+            started: 0,
+        }
+    }
+    fn is_cancelled(&self) -> bool {
+        self.generation.load(Ordering::Relaxed) != self.started
+    }
+}
+
+// samples of one tile, with their coordinates
+type Computed = Vec<((u16, u16), Escape)>;
+
+// what a background render sends back to the screen
+enum Update {
+    Tile(Computed),
+    Finished(Box<PerturbationState>),
+    Failed(Error),
+}
+
+// the result of a headless render, one escape per sample in row major order
+pub struct Grid {
+    pub size: (u16, u16),
+    values: Vec<Escape>,
+}
+
+impl Grid {
+    pub fn get(&self, x: u16, y: u16) -> Result<Escape> {
+        if x < self.size.0 && y < self.size.1 {
+            return Ok(self.values[y as usize * self.size.0 as usize + x as usize]);
+        }
+        Err(Error::OutOfBounds { x, y, size: self.size })
+    }
+}
+
+// fixed point once f64 cannot resolve the samples, if the fractal has a fixed point path at all
+pub fn precision(fractal: &dyn Fractal, viewport: &Viewport) -> Precision {
+    if fractal.supports_fixed() || fractal.supports_perturbation() {
+        return viewport.precision();
+    }
+    Precision::Double
+}
+
+fn point(viewport: &Viewport, precision: Precision, x: u16, y: u16, size: (u16, u16)) -> Point {
+    match precision {
+        Precision::Double => Point::Double(viewport.point(x as f64, y as f64, size)),
+        Precision::Fixed { fraction_limbs } => {
+            Point::Fixed(Box::new(viewport.point_fixed(x as f64, y as f64, size, fraction_limbs)))
+        }
+    }
+}
+
+// computes every sample of a size.0 x size.1 grid covering the viewport, without a terminal
+pub fn render_grid(
+    fractal: &Arc<dyn Fractal>,
+    viewport: &Viewport,
+    size: (u16, u16),
+    n_iter: u16,
+) -> Result<Grid> {
+    let mut samples = Vec::with_capacity(size.0 as usize * size.1 as usize);
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            samples.push((x, y));
+        }
+    }
+    let placeholder = Escape {
+        iterations: 0,
+        smooth: 0.0,
+        norm_sqr: 0.0,
+        interior: false,
+        basin: None,
+        period: None,
+    };
+    let mut grid = Grid {
+        size,
+        values: vec![placeholder; samples.len()],
+    };
+    let mut state = PerturbationState::default();
+    compute_samples(fractal, viewport, size, samples, n_iter, &mut state, &Cancel::never(), |tile| {
+        for ((x, y), escape) in tile {
+            grid.values[y as usize * size.0 as usize + x as usize] = escape;
+        }
+        Ok(())
+    })?;
+    Ok(grid)
+}
+
+// a grid of character cells showing the fractal's default view, for small previews
+pub fn render_preview(fractal: &Arc<dyn Fractal>, size: (u16, u16), n_iter: u16) -> Result<Grid> {
+    let view = fractal.default_view();
+    let aspect = RenderMode::Characters.sample_aspect();
+    let scale = f64::max(2.0 * view.radius / size.0 as f64, 2.0 * view.radius / (size.1 as f64 * aspect));
+    render_grid(fractal, &Viewport::new(view.center, scale, aspect), size, n_iter)
+}
+
+// computes the samples of the screen buffer that need rendering on a thread of its own, so that
+// input keeps being read. the tiles are put into the buffer as they arrive, and starting the next render
+// cancels this one while keeping every tile it already delivered
+pub struct Background {
+    generation: Arc<AtomicU64>,
+    updates: Option<Receiver<Update>>,
+    rendering: Option<(Arc<dyn Fractal>, u16)>, // what is being rendered, to describe it in the status bar when done
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::new()
+    }
+}
+
+impl Background {
+    pub fn new() -> Background {
+        Background {
+            generation: Arc::new(AtomicU64::new(0)),
+            updates: None,
+            rendering: None,
+        }
+    }
+    // stops the workers from starting any more samples of the render in progress
+    pub fn cancel(&mut self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.updates = None;
+    }
+    pub fn start(&mut self, screen: &mut screen::Screen, fractal: &Arc<dyn Fractal>, n_iter: u16) -> Result<()> {
+        self.start_frame(&screen.buffer, screen.viewport, &mut screen.perturbation, fractal, n_iter)
+    }
+    // the samples of the buffer that need rendering, without a terminal behind them
+    fn start_frame(
+        &mut self,
+        buffer: &cyclic_buffer::Buffer<cyclic_buffer::Pixel>,
+        viewport: Viewport,
+        perturbation: &mut PerturbationState,
+        fractal: &Arc<dyn Fractal>,
+        n_iter: u16,
+    ) -> Result<()> {
+        self.cancel();
+        let size = buffer.size;
+        let mut samples = Vec::new();
+        for x in 0..size.0 {
+            for y in 0..size.1 {
+                if buffer.get(x, y)?.needs_render() {
+                    samples.push((x, y));
+                }
+            }
+        }
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let cancel = Cancel {
+            generation: Arc::clone(&self.generation),
+            started: self.generation.load(Ordering::Relaxed),
+        };
+        // the reference orbit is handed back when the render finishes, a cancelled render keeps the old one
+        perturbation.finished = false;
+        let mut state = perturbation.clone();
+        self.rendering = Some((Arc::clone(fractal), n_iter));
+        let fractal = Arc::clone(fractal);
+        let (tx, rx) = mpsc::channel();
+        self.updates = Some(rx);
+        thread::Builder::new()
+            .name(String::from("render"))
+            .spawn(move || {
+                let result = compute_samples(&fractal, &viewport, size, samples, n_iter, &mut state, &cancel, |tile| {
+                    tx.send(Update::Tile(tile)).map_err(|_| Error::Cancelled)
+                });
+                state.finished = true;
+                let _ = match result {
+                    Ok(()) => tx.send(Update::Finished(Box::new(state))),
+                    Err(Error::Cancelled) => Ok(()),
+                    Err(error) => tx.send(Update::Failed(error)),
+                };
+            })
+            .map_err(Error::io("start the render thread"))?;
+        Ok(())
+    }
+    pub fn is_running(&self) -> bool {
+        self.updates.is_some()
+    }
+    // puts the tiles that arrived since the last call into the buffer, true if there were any or the render finished.
+    // a finished render also brings the status bar up to date
+    pub fn receive(&mut self, screen: &mut screen::Screen) -> Result<bool> {
+        let (received, finished) = self.receive_frame(&mut screen.buffer, &mut screen.perturbation)?;
+        if finished {
+            if let Some((fractal, n_iter)) = self.rendering.take() {
+                stat_bar::render_status_bar(screen, fractal.as_ref(), n_iter);
+            }
+        }
+        Ok(received)
+    }
+    // the buffer side of receive, also telling whether the render finished
+    fn receive_frame(
+        &mut self,
+        buffer: &mut cyclic_buffer::Buffer<cyclic_buffer::Pixel>,
+        perturbation: &mut PerturbationState,
+    ) -> Result<(bool, bool)> {
+        let mut received = false;
+        let mut finished = false;
+        while let Some(updates) = &self.updates {
+            match updates.try_recv() {
+                Ok(Update::Tile(tile)) => {
+                    for ((x, y), escape) in tile {
+                        buffer.put(cyclic_buffer::Pixel::Value(escape), x, y)?;
+                    }
+                    received = true;
+                }
+                Ok(Update::Finished(state)) => {
+                    *perturbation = *state;
+                    self.updates = None;
+                    received = true;
+                    finished = true;
+                }
+                Ok(Update::Failed(error)) => {
+                    self.updates = None;
+                    return Err(error);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.updates = None;
+                    return Err(Error::Worker("the render thread stopped without finishing"));
+                }
+            }
+        }
+        Ok((received, finished))
+    }
+}
+
+// computes samples of a size.0 x size.1 grid covering the viewport, in the precision the viewport needs
+#[allow(clippy::too_many_arguments)]
+fn compute_samples<F>(
+    fractal: &Arc<dyn Fractal>,
+    viewport: &Viewport,
+    size: (u16, u16),
+    samples: Vec<(u16, u16)>,
+    n_iter: u16,
+    state: &mut PerturbationState,
+    cancel: &Cancel,
+    mut put: F,
+) -> Result<()>
+where
+    F: FnMut(Computed) -> Result<()>,
+{
+    if samples.is_empty() {
+        return Ok(());
+    }
+    let precision = precision(fractal.as_ref(), viewport);
+    state.references_used = 0;
+    state.skipped = 0;
+    if let Precision::Fixed { fraction_limbs } = precision {
+        if fractal.supports_perturbation() {
+            return perturb(fractal, viewport, size, samples, n_iter, fraction_limbs, state, cancel, put);
+        }
+    }
+    let points = samples
+        .into_iter()
+        .map(|(x, y)| (point(viewport, precision, x, y, size), (x, y)))
+        .collect();
+    compute_points(fractal, points, n_iter, cancel, |tile| put(unglitched(tile)?))
+}
+
+// every sample as a difference to a reference orbit near the center. samples that glitch get another
+// reference from among themselves, and the few left after that are computed in fixed point
+#[allow(clippy::too_many_arguments)]
+fn perturb<F>(
+    fractal: &Arc<dyn Fractal>,
+    viewport: &Viewport,
+    size: (u16, u16),
+    samples: Vec<(u16, u16)>,
+    n_iter: u16,
+    fraction_limbs: usize,
+    state: &mut PerturbationState,
+    cancel: &Cancel,
+    mut put: F,
+) -> Result<()>
+where
+    F: FnMut(Computed) -> Result<()>,
+{
+    let center = viewport.center_fixed.with_fraction_limbs(fraction_limbs);
+    let reach = REFERENCE_REACH * viewport.scale * std::cmp::max(size.0, size.1) as f64;
+    let reusable = state.reference.as_ref().filter(|reference| {
+        Arc::ptr_eq(&reference.fractal, fractal)
+            && reference.n_iter == n_iter
+            && reference.c.fraction_limbs() == fraction_limbs
+            && center.sub(&reference.c).to_f64().norm() < reach
+    });
+    let mut reference = match reusable {
+        Some(reference) => reference.clone(),
+        None => Reference::new(fractal, center, n_iter),
+    };
+    state.reference = Some(reference.clone());
+
+    let mut pending = samples;
+    for round in 0..MAX_REFERENCES {
+        if round > 0 {
+            // a glitched sample does not behave like the old reference, so it makes a good new one
+            let (x, y) = pending[pending.len() / 2];
+            reference = Reference::new(fractal, viewport.point_fixed(x as f64, y as f64, size, fraction_limbs), n_iter);
+        }
+        state.references_used += 1;
+        // the center relative to the reference is small, so f64 holds it to full relative precision
+        let shift = center.sub(&reference.c).to_f64();
+        let dc = |x: f64, y: f64| shift + viewport.offset(x - size.0 as f64 / 2.0, y - size.1 as f64 / 2.0);
+        // the series is checked on the corners and edges of the view, which are furthest from the reference
+        let series = if round == 0 {
+            let (w, h) = (size.0.saturating_sub(1) as f64, size.1.saturating_sub(1) as f64);
+            let probes: Vec<Complex<f64>> = [(0.0, 0.0), (0.5, 0.0), (1.0, 0.0), (0.0, 0.5), (1.0, 0.5), (0.0, 1.0), (0.5, 1.0), (1.0, 1.0)]
+                .iter()
+                .map(|(x, y)| dc(x * w, y * h))
+                .collect();
+            let series = fractal.series(&reference.orbit, &probes, n_iter);
+            state.skipped = series.skipped;
+            series
+        } else {
+            Series::NONE
+        };
+        let perturbed = Arc::new(Perturbed {
+            orbit: Arc::clone(&reference.orbit),
+            series,
+        });
+        let points = pending
+            .iter()
+            .map(|&(x, y)| (Point::Delta(Arc::clone(&perturbed), dc(x as f64, y as f64)), (x, y)))
+            .collect();
+        let mut glitched = Vec::new();
+        compute_points(fractal, points, n_iter, cancel, |tile| {
+            let mut computed = Vec::with_capacity(tile.len());
+            for px in tile {
+                match px.value {
+                    Some(escape) => computed.push((px.coords, escape)),
+                    None => glitched.push(px.coords),
+                }
+            }
+            put(computed)
+        })?;
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        pending = glitched;
+        if pending.is_empty() {
+            return Ok(());
+        }
+    }
+    let points = pending
+        .into_iter()
+        .map(|(x, y)| (point(viewport, Precision::Fixed { fraction_limbs }, x, y, size), (x, y)))
+        .collect();
+    compute_points(fractal, points, n_iter, cancel, |tile| put(unglitched(tile)?))
+}
+
+// samples are handed to the workers in square tiles of this many samples across. small enough that
+// the slow tiles of the interior spread over all threads, large enough that each is worth a message
+const TILE_SIZE: u16 = 16;
+
+type Tile = Vec<(Point, (u16, u16))>;
+
+// the samples of a tile that cannot have glitched
+fn unglitched(tile: Vec<PixelWithCoords>) -> Result<Computed> {
+    tile.into_iter()
+        .map(|px| match px.value {
+            Some(escape) => Ok((px.coords, escape)),
+            None => Err(Error::RenderState("only perturbed points can glitch")),
+        })
+        .collect()
+}
+
+// groups the points into tiles for the worker pool, and hands each tile to put as it arrives
+fn compute_points<F>(
+    fractal: &Arc<dyn Fractal>,
+    coords_to_draw: Vec<(Point, (u16, u16))>,
+    n_iter: u16,
+    cancel: &Cancel,
+    mut put: F,
+) -> Result<()>
+where
+    F: FnMut(Vec<PixelWithCoords>) -> Result<()>,
+{
+    let mut tiles: BTreeMap<(u16, u16), Tile> = BTreeMap::new();
+    for point in coords_to_draw {
+        let (x, y) = point.1;
+        tiles.entry((y / TILE_SIZE, x / TILE_SIZE)).or_default().push(point);
+    }
+    let n_tiles = tiles.len();
+
+    let (tx, rx) = mpsc::channel::<Vec<PixelWithCoords>>();
+    let pool = pool::global();
+    for tile in tiles.into_values() {
+        let local_tx = tx.clone();
+        let fractal = Arc::clone(fractal);
+        let cancel = cancel.clone();
+        pool.execute(move || {
+            let mut pixels = Vec::with_capacity(tile.len());
+            for (point, coords) in tile {
+                // a cancelled tile is dropped whole, its samples stay marked for the next render
+                if cancel.is_cancelled() {
+                    return;
+                }
+                let value = match &point {
+                    Point::Double(point) => Some(fractal.compute(*point, n_iter)),
+                    Point::Fixed(point) => Some(fractal.compute_fixed(point, n_iter)),
+                    Point::Delta(perturbed, dc) => {
+                        fractal.compute_perturbed(&perturbed.orbit, &perturbed.series, *dc, n_iter)
+                    }
+                };
+                pixels.push(PixelWithCoords { coords, value });
+            }
+            // the receiving end only goes away if the render was abandoned
+            let _ = local_tx.send(pixels);
+        });
+    }
+    // without this the channel never closes, and a tile that panicked or was cancelled would hang the loop below
+    drop(tx);
+
+    for _i in 0..n_tiles {
+        match rx.recv() {
+            Ok(pixels) => put(pixels)?,
+            Err(_e) if cancel.is_cancelled() => return Err(Error::Cancelled),
+            Err(_e) => {
+                return Err(Error::Worker("a render tile stopped before sending its pixels"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::DefaultView;
+    use crate::mandelbrot::{Mandelbrot, QUADRATIC};
+    use crate::terminal::cyclic_buffer::{Buffer, Pixel};
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::time::{Duration, Instant};
+
+    // a deep view into seahorse valley, where the series can skip most of the iterations
+    const DEEP_CENTER: Complex<f64> = Complex::new(-0.743643887037151, 0.131825904205330);
+    const DEEP_SCALE: f64 = 1e-14;
+    const DEEP_SIZE: (u16, u16) = (40, 20);
+    const DEEP_ITER: u16 = 3000;
+
+    // the mandelbrot set with perturbation, but without a series to skip iterations with
+    struct WithoutSeries(Mandelbrot);
+
+    impl Fractal for WithoutSeries {
+        fn name(&self) -> String {
+            self.0.name()
+        }
+        fn default_view(&self) -> DefaultView {
+            self.0.default_view()
+        }
+        fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape {
+            self.0.compute(c, n_iter)
+        }
+        fn supports_fixed(&self) -> bool {
+            self.0.supports_fixed()
+        }
+        fn compute_fixed(&self, c: &FixedComplex, n_iter: u16) -> Escape {
+            self.0.compute_fixed(c, n_iter)
+        }
+        fn supports_perturbation(&self) -> bool {
+            self.0.supports_perturbation()
+        }
+        fn reference_orbit(&self, c: &FixedComplex, n_iter: u16) -> Vec<Complex<f64>> {
+            self.0.reference_orbit(c, n_iter)
+        }
+        fn compute_perturbed(&self, orbit: &[Complex<f64>], series: &Series, dc: Complex<f64>, n_iter: u16) -> Option<Escape> {
+            self.0.compute_perturbed(orbit, series, dc, n_iter)
+        }
+    }
+
+    fn mandelbrot() -> Arc<dyn Fractal> {
+        Arc::new(Mandelbrot { d: QUADRATIC })
+    }
+
+    // every sample of the view, together with what the perturbation state recorded about the render
+    fn render(fractal: &Arc<dyn Fractal>, viewport: &Viewport, size: (u16, u16), n_iter: u16) -> (Vec<Escape>, PerturbationState) {
+        let samples: Vec<(u16, u16)> = (0..size.1).flat_map(|y| (0..size.0).map(move |x| (x, y))).collect();
+        let mut values = vec![None; samples.len()];
+        let mut state = PerturbationState::default();
+        compute_samples(fractal, viewport, size, samples, n_iter, &mut state, &Cancel::never(), |tile| {
+            for ((x, y), escape) in tile {
+                values[y as usize * size.0 as usize + x as usize] = Some(escape);
+            }
+            Ok(())
+        })
+        .unwrap();
+        (values.into_iter().map(|value| value.expect("every sample is computed")).collect(), state)
+    }
+
+    fn deep_view() -> Viewport {
+        Viewport::new(DEEP_CENTER, DEEP_SCALE, 1.0)
+    }
+
+    #[test]
+    fn the_series_skips_iterations_without_changing_them() {
+        let viewport = deep_view();
+        let (with_series, state) = render(&mandelbrot(), &viewport, DEEP_SIZE, DEEP_ITER);
+        assert!(state.skipped > 100, "only {} iterations skipped", state.skipped);
+        let without: Arc<dyn Fractal> = Arc::new(WithoutSeries(Mandelbrot { d: QUADRATIC }));
+        let (without_series, state) = render(&without, &viewport, DEEP_SIZE, DEEP_ITER);
+        assert_eq!(state.skipped, 0);
+        for (index, (with, without)) in with_series.iter().zip(&without_series).enumerate() {
+            let (x, y) = (index % DEEP_SIZE.0 as usize, index / DEEP_SIZE.0 as usize);
+            assert!(
+                (with.iterations as i32 - without.iterations as i32).abs() <= 1,
+                "({}, {}) took {} iterations with the series and {} without",
+                x,
+                y,
+                with.iterations,
+                without.iterations
+            );
+        }
+    }
+
+    // every sample of a deep view against the same point iterated directly in fixed point. perturbation rounds
+    // differently, which is allowed to move the escape by one iteration
+    fn assert_matches_fixed_point(viewport: &Viewport, size: (u16, u16), n_iter: u16) -> PerturbationState {
+        let fraction_limbs = match viewport.precision() {
+            Precision::Fixed { fraction_limbs } => fraction_limbs,
+            Precision::Double => panic!("the view is not deep enough for perturbation"),
+        };
+        let fractal = mandelbrot();
+        let (values, state) = render(&fractal, viewport, size, n_iter);
+        assert!(state.references_used > 0);
+        for (index, escape) in values.iter().enumerate() {
+            let (x, y) = ((index % size.0 as usize) as u16, (index / size.0 as usize) as u16);
+            let expected = fractal.compute_fixed(&viewport.point_fixed(x as f64, y as f64, size, fraction_limbs), n_iter);
+            assert!(
+                (escape.iterations as i32 - expected.iterations as i32).abs() <= 1,
+                "({}, {}) took {} iterations perturbed and {} in fixed point",
+                x,
+                y,
+                escape.iterations,
+                expected.iterations
+            );
+        }
+        state
+    }
+
+    #[test]
+    fn perturbation_matches_fixed_point() {
+        assert_matches_fixed_point(&deep_view(), (20, 10), 2000);
+    }
+
+    #[test]
+    fn glitched_samples_fall_back_to_other_references() {
+        // near the tip of the needle enough samples glitch against the central reference to need others
+        let viewport = Viewport::new(Complex::new(-1.99, 0.0), DEEP_SCALE, 1.0);
+        let state = assert_matches_fixed_point(&viewport, (20, 10), 2000);
+        assert!(state.references_used > 1, "no sample glitched");
+    }
+
+    // a background render of two tiles side by side, the left one of negative and the right one of positive points
+    const GATED_SIZE: (u16, u16) = (2 * TILE_SIZE, TILE_SIZE);
+    // long enough to never run out in a working test, short enough that a broken one still finishes
+    const WAIT: Duration = Duration::from_secs(10);
+
+    // returns n_iter for every point, so that samples show which render they came from. points of the right tile
+    // only return once the gate is open, which keeps the right tile unfinished for as long as a test needs
+    struct Gated {
+        open: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Gated {
+        // the fractal together with its gate and the number of points it was asked for
+        fn fractal(open: bool) -> (Arc<dyn Fractal>, Arc<AtomicBool>, Arc<AtomicUsize>) {
+            let gated = Gated {
+                open: Arc::new(AtomicBool::new(open)),
+                calls: Arc::new(AtomicUsize::new(0)),
+            };
+            let (open, calls) = (Arc::clone(&gated.open), Arc::clone(&gated.calls));
+            (Arc::new(gated), open, calls)
+        }
+    }
+
+    impl Fractal for Gated {
+        fn name(&self) -> String {
+            String::from("gated")
+        }
+        fn default_view(&self) -> DefaultView {
+            DefaultView {
+                center: Complex::new(0.0, 0.0),
+                radius: 1.0,
+            }
+        }
+        fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let started = Instant::now();
+            while c.re >= 0.0 && !self.open.load(Ordering::SeqCst) && started.elapsed() < WAIT {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Escape::bounded(n_iter, 0.0)
+        }
+    }
+
+    fn gated_view() -> Viewport {
+        Viewport::new(Complex::new(0.0, 0.0), 0.1, 1.0)
+    }
+
+    // receives until done says the buffer is complete
+    fn receive_until<F>(background: &mut Background, buffer: &mut Buffer<Pixel>, state: &mut PerturbationState, done: F)
+    where
+        F: Fn(&Buffer<Pixel>, bool) -> bool,
+    {
+        let started = Instant::now();
+        loop {
+            let (_, finished) = background.receive_frame(buffer, state).unwrap();
+            if done(buffer, finished) {
+                return;
+            }
+            assert!(started.elapsed() < WAIT, "the render never got there");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // the iterations of every sample in one half of the buffer, None for those that still need rendering
+    fn half(buffer: &Buffer<Pixel>, right: bool) -> Vec<Option<u16>> {
+        let offset = if right { TILE_SIZE } else { 0 };
+        let mut values = Vec::new();
+        for x in offset..offset + TILE_SIZE {
+            for y in 0..GATED_SIZE.1 {
+                values.push(match buffer.get(x, y).unwrap() {
+                    Pixel::Value(escape) => Some(escape.iterations),
+                    _ => None,
+                });
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn cancelling_keeps_the_finished_tiles() {
+        let (first, open, _) = Gated::fractal(false);
+        let mut background = Background::new();
+        let mut buffer = Buffer::new(GATED_SIZE, Pixel::Recompute);
+        let mut state = PerturbationState::default();
+        background.start_frame(&buffer, gated_view(), &mut state, &first, 10).unwrap();
+        receive_until(&mut background, &mut buffer, &mut state, |buffer, _| half(buffer, false).iter().all(Option::is_some));
+        background.cancel();
+        open.store(true, Ordering::SeqCst);
+        assert!(!background.is_running());
+        assert!(half(&buffer, false).iter().all(|value| *value == Some(10)));
+        assert!(half(&buffer, true).iter().all(Option::is_none));
+
+        // the next render only computes what the cancelled one left undone
+        let (second, _, calls) = Gated::fractal(true);
+        background.start_frame(&buffer, gated_view(), &mut state, &second, 20).unwrap();
+        receive_until(&mut background, &mut buffer, &mut state, |_, finished| finished);
+        assert_eq!(calls.load(Ordering::SeqCst), (TILE_SIZE * TILE_SIZE) as usize);
+        assert!(half(&buffer, false).iter().all(|value| *value == Some(10)));
+        assert!(half(&buffer, true).iter().all(|value| *value == Some(20)));
+    }
+
+    #[test]
+    fn tiles_of_a_replaced_render_are_ignored() {
+        let (first, open, first_calls) = Gated::fractal(false);
+        let mut background = Background::new();
+        let mut buffer = Buffer::new(GATED_SIZE, Pixel::Recompute);
+        let mut state = PerturbationState::default();
+        background.start_frame(&buffer, gated_view(), &mut state, &first, 10).unwrap();
+        // the left tile is done and waiting to be received once the right one is stuck behind the gate
+        let started = Instant::now();
+        while first_calls.load(Ordering::SeqCst) <= (TILE_SIZE * TILE_SIZE) as usize {
+            assert!(started.elapsed() < WAIT, "the first render never reached the gate");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // starting the next render moves the generation on, nothing of the first may reach the buffer after that
+        let (second, _, _) = Gated::fractal(true);
+        background.start_frame(&buffer, gated_view(), &mut state, &second, 20).unwrap();
+        open.store(true, Ordering::SeqCst);
+        receive_until(&mut background, &mut buffer, &mut state, |_, finished| finished);
+        assert!(half(&buffer, false).iter().all(|value| *value == Some(20)));
+        assert!(half(&buffer, true).iter().all(|value| *value == Some(20)));
+        assert_eq!(first_calls.load(Ordering::SeqCst), (TILE_SIZE * TILE_SIZE) as usize + 1);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::fractal::Escape;
+
+pub const IN_FRACTAL: char = '*';
+pub const OUTSIDE_FRACTAL: char = ' ';
+// characters ordered by visual density, used for points outside the set
+const DENSITY_RAMP: [char; 10] = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+// one character per basin of a convergence based fractal, repeating if there are more basins
+const BASIN_GLYPHS: [char; 6] = ['o', 'x', '+', '#', '%', '='];
+
+// turns computed escape data into something that can be drawn, kept separate from
+// the computation so the look can change without re-rendering the fractal
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Shading {
+    Binary, // the set is drawn with IN_FRACTAL, everything else is blank, basins get a character each
+    Ramp,   // the exterior is shaded by escape iteration, the set itself is blank
+}
+
+impl Shading {
+    pub fn next(self) -> Shading {
+        match self {
+            Shading::Binary => Shading::Ramp,
+            Shading::Ramp => Shading::Binary,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Shading::Binary => "binary",
+            Shading::Ramp => "ramp",
+        }
+    }
+    pub fn glyph(self, escape: &Escape, n_iter: u16, smooth: bool) -> char {
+        match self {
+            Shading::Binary => match escape.basin {
+                Some(basin) => BASIN_GLYPHS[basin as usize % BASIN_GLYPHS.len()],
+                None if escape.interior => IN_FRACTAL,
+                None => OUTSIDE_FRACTAL,
+            },
+            Shading::Ramp => {
+                if escape.interior {
+                    return OUTSIDE_FRACTAL;
+                }
+                // log scale, so the slowly escaping points near the boundary stay distinguishable
+                let t = (1.0 + escape.iteration(smooth)).ln() / (1.0 + n_iter as f64).ln();
+                let index = (t * (DENSITY_RAMP.len() - 1) as f64).round() as usize;
+                DENSITY_RAMP[index.min(DENSITY_RAMP.len() - 1)]
+            }
+        }
+    }
+}
@@ -1,51 +1,58 @@
-use crate::terminal::{
-    screen, 
-    cyclic_buffer
-};
+use crate::fractal::Fractal;
+use crate::palette::PALETTES;
+use crate::render;
+use crate::terminal::screen;
+use crate::viewport::Precision;
 
-pub fn render_status_bar(screen: &mut screen::Screen, n_iter: u16) -> Result<(), &'static str> {
+pub fn render_status_bar(screen: &mut screen::Screen, fractal: &dyn Fractal, n_iter: u16) {
     // should indicate the current scale, position, fps eventually
-    // stat bar is a box in the top left
-    let scale_str = String::from(format!("Scale (log10): {}", -screen.scale.log10()));
-    let position_string = String::from(format!("Position: {:.7} + i*{:.7}", screen.center.re, screen.center.im));
-    let iteration_string = String::from(format!("Number of iterations: {}", n_iter));
+    // the lines are drawn as a box in the top left by the screen, on top of the fractal
+    let viewport = screen.viewport;
+    let mut fractal_string = format!("Fractal: {}", fractal.name());
+    for parameter in fractal.parameters() {
+        fractal_string.push_str(&format!(", {} = {:.5} + i*{:.5}", parameter.name, parameter.value.re, parameter.value.im));
+    }
+    let scale_str = format!("Scale (log10): {}", -viewport.scale.log10());
+    // enough digits to tell neighbouring samples apart
+    let digits = std::cmp::max(7, 2 - viewport.scale.log10() as i32) as usize;
+    let position_string = format!(
+        "Position: {} + i*{}",
+        viewport.center_fixed.re.to_decimal(digits),
+        viewport.center_fixed.im.to_decimal(digits)
+    );
+    let precision_string = match (render::precision(fractal, &viewport), viewport.precision()) {
+        (Precision::Double, Precision::Fixed { .. }) => String::from("Precision: f64 (no fixed point path for this fractal)"),
+        (precision @ Precision::Fixed { .. }, _) if fractal.supports_perturbation() && !screen.perturbation.finished => {
+            format!("Precision: {} references, f64 perturbation (rendering)", precision.name())
+        }
+        (precision @ Precision::Fixed { .. }, _) if fractal.supports_perturbation() => format!(
+            "Precision: {} references, f64 perturbation ({} references, {} iterations skipped)",
+            precision.name(),
+            screen.perturbation.references_used,
+            screen.perturbation.skipped
+        ),
+        (precision, _) => format!("Precision: {}", precision.name()),
+    };
+    let rotation_string = format!("Rotation: {:.0} degrees", viewport.rotation.to_degrees());
+    let iteration_string = format!("Number of iterations: {}", n_iter);
+    let shading_string = format!("Shading: {} ({})", screen.shading.name(), if screen.smooth { "smooth" } else { "banded" });
+    let palette_string = format!("Palette: {} ({} colours)", PALETTES[screen.palette].name, screen.color_depth.name());
+    let mode_string = format!("Render mode: {}", screen.render_mode.name());
 
-    let strings_to_render = vec![
+    let mut lines = vec![fractal_string];
+    lines.extend(fractal.describe());
+    lines.extend([
         scale_str, 
         position_string,
+        precision_string,
+        rotation_string,
         iteration_string, 
-    ];
-
-    let max_width = strings_to_render.iter().map(|string: &String| string.len()).max().unwrap();
-    let height = strings_to_render.len();
-    // horisontal bars
-    for x in 1..max_width + 1 {
-        screen.putchar(x as u16, 0, cyclic_buffer::Pixel::StatBar('-'))?;
-        screen.putchar(x as u16, (height + 1) as u16, cyclic_buffer::Pixel::StatBar('-'))?;
-    }
-    // vertical bars
-    for y in 1..height + 1 {
-        screen.putchar(0, y as u16, cyclic_buffer::Pixel::StatBar('|'))?;
-        screen.putchar((max_width + 1) as u16, y as u16, cyclic_buffer::Pixel::StatBar('|'))?;
-    }
-    // rows of text
-    for index in 0..strings_to_render.len() {
-        for x in 0..strings_to_render[index].len() {
-            screen.putchar((x + 1) as u16, (index + 1) as u16, cyclic_buffer::Pixel::StatBar(strings_to_render[index].chars().nth(x).unwrap()))?;
-        }
-    }
-    
-    Ok(())
-}
-
-pub fn clear_stat_bar(screen: &mut screen::Screen) -> Result<(), &'static str> {
-    let (w, h) = screen.buffer.size;
-    for x in 0..w {
-        for y in 0..h {
-            if let cyclic_buffer::Pixel::StatBar(_) = screen.buffer.get(x, y)? {
-                screen.buffer.put(cyclic_buffer::Pixel::Recompute, x, y)?;
-            }
-        }
+        shading_string,
+        palette_string,
+        mode_string,
+    ]);
+    if let Some(prompt) = &screen.prompt {
+        lines.extend(prompt.lines());
     }
-    Ok(())
+    screen.stat_bar = lines;
 }
@@ -1,14 +1,545 @@
+use crate::error::{Error, Result};
 use crate::terminal::screen;
 use std::io::Read;
+use std::time::{Duration, Instant};
 
-pub fn get_char(screen: &mut screen::Screen) -> Option<char>{
+const ESC: u8 = 0x1b;
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+// an unfinished sequence older than this was a lone escape key. long enough for the rest of
+// a sequence to arrive over a slow connection, short enough that Esc still feels immediate
+const ESC_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Insert,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    BackTab,
+    Esc,
+    F(u8),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers {
+        shift: false,
+        alt: false,
+        ctrl: false,
+    };
+    // xterm encodes modifiers as 1 + a bitmask in the second csi parameter
+    fn from_csi(param: u32) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        Modifiers {
+            shift: bits & 1 != 0,
+            alt: bits & 2 != 0,
+            ctrl: bits & 4 != 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MouseKind {
+    Press(MouseButton),
+    Release(MouseButton),
+    Drag(MouseButton), // the pointer moved while the button was held
+    Move,              // the pointer moved with no button held
+    ScrollUp,
+    ScrollDown,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub x: u16, // terminal cell, starting from 0
+    pub y: u16,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    Key(Key, Modifiers),
+    Paste(String), // text pasted while bracketed paste mode is on
+    Mouse(Mouse),  // reported in the sgr (1006) encoding
+}
+
+// what happened when trying to decode the start of the pending bytes
+enum Decoded {
+    Event(Event, usize), // an event and how many bytes it used
+    Skip(usize),         // bytes that do not mean anything to us
+    Incomplete,          // the sequence might continue in the next read
+}
+
+// turns the raw bytes from stdin into events. sequences can be split across reads,
+// so whatever could not be decoded yet is kept until more bytes arrive
+#[derive(Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+    incomplete_since: Option<Instant>, // when the unfinished sequence at the start of pending arrived
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        self.feed_at(bytes, Instant::now())
+    }
+    // an unfinished sequence that nothing completed within ESC_TIMEOUT is really a lone escape key,
+    // possibly followed by other keys. a paste is the exception, it can take any number of reads
+    fn feed_at(&mut self, bytes: &[u8], now: Instant) -> Vec<Event> {
+        let expired = self.incomplete_since.is_some_and(|since| now.duration_since(since) >= ESC_TIMEOUT);
+        self.pending.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        let mut start = 0;
+        while start < self.pending.len() {
+            match decode(&self.pending[start..]) {
+                Decoded::Event(event, used) => {
+                    events.push(event);
+                    start += used;
+                }
+                Decoded::Skip(used) => start += used,
+                // only the sequence that was already waiting has timed out, not one that just arrived
+                Decoded::Incomplete if expired && start == 0 && !self.pending.starts_with(PASTE_START) => {
+                    if self.pending[start] == ESC {
+                        events.push(Event::Key(Key::Esc, Modifiers::NONE));
+                    }
+                    start += 1;
+                }
+                Decoded::Incomplete => break,
+            }
+        }
+        self.pending.drain(..start);
+        if self.pending.is_empty() {
+            self.incomplete_since = None;
+        } else if start > 0 || self.incomplete_since.is_none() {
+            self.incomplete_since = Some(now);
+        }
+        events
+    }
+}
+
+// reads everything that is waiting on stdin and decodes it
+pub fn get_events(screen: &mut screen::Screen) -> Result<Vec<Event>> {
     let mut char_buffer: Vec<u8> = Vec::new();
-    if let Err(_e) = screen.stdin.read_to_end(&mut char_buffer) {
-        panic!("Error reading to stdin ")
+    screen
+        .stdin
+        .read_to_end(&mut char_buffer)
+        .map_err(Error::io("read from stdin"))?;
+    Ok(screen.input.feed(&char_buffer))
+}
+
+fn key(key: Key, used: usize) -> Decoded {
+    Decoded::Event(Event::Key(key, Modifiers::NONE), used)
+}
+
+fn decode(bytes: &[u8]) -> Decoded {
+    match bytes[0] {
+        ESC => decode_escape(bytes),
+        b'\r' | b'\n' => key(Key::Enter, 1),
+        b'\t' => key(Key::Tab, 1),
+        0x7f | 0x08 => key(Key::Backspace, 1),
+        // ctrl + letter arrives as the letter's position in the alphabet
+        c @ 0x01..=0x1a => Decoded::Event(
+            Event::Key(
+                Key::Char((b'a' + c - 1) as char),
+                Modifiers {
+                    ctrl: true,
+                    ..Modifiers::NONE
+                },
+            ),
+            1,
+        ),
+        _ => decode_utf8(bytes),
+    }
+}
+
+fn decode_utf8(bytes: &[u8]) -> Decoded {
+    let length = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Decoded::Skip(1),
+    };
+    if bytes.len() < length {
+        return Decoded::Incomplete;
+    }
+    match std::str::from_utf8(&bytes[..length]) {
+        Ok(s) => match s.chars().next() {
+            Some(c) if !c.is_control() => key(Key::Char(c), length),
+            _ => Decoded::Skip(length),
+        },
+        Err(_) => Decoded::Skip(1),
+    }
+}
+
+fn decode_escape(bytes: &[u8]) -> Decoded {
+    if bytes.len() < 2 {
+        return Decoded::Incomplete;
+    }
+    match bytes[1] {
+        b'[' => decode_csi(bytes),
+        b'O' => {
+            if bytes.len() < 3 {
+                return Decoded::Incomplete;
+            }
+            match ss3_key(bytes[2]) {
+                Some(k) => key(k, 3),
+                None => Decoded::Skip(3),
+            }
+        }
+        ESC => key(Key::Esc, 1),
+        // alt + key arrives as escape followed by the key
+        _ => match decode(&bytes[1..]) {
+            Decoded::Event(Event::Key(k, modifiers), used) => Decoded::Event(
+                Event::Key(
+                    k,
+                    Modifiers {
+                        alt: true,
+                        ..modifiers
+                    },
+                ),
+                used + 1,
+            ),
+            Decoded::Event(event, used) => Decoded::Event(event, used + 1),
+            Decoded::Skip(used) => Decoded::Skip(used + 1),
+            Decoded::Incomplete => Decoded::Incomplete,
+        },
+    }
+}
+
+fn ss3_key(c: u8) -> Option<Key> {
+    match c {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        b'H' => Some(Key::Home),
+        b'F' => Some(Key::End),
+        b'P'..=b'S' => Some(Key::F(c - b'P' + 1)),
+        _ => None,
+    }
+}
+
+// ESC [ parameters final, where the parameters are digits separated by ';'
+fn decode_csi(bytes: &[u8]) -> Decoded {
+    if bytes.starts_with(PASTE_START) {
+        return decode_paste(bytes);
+    }
+    if bytes.len() < 3 {
+        return Decoded::Incomplete;
+    }
+    if bytes[2] == b'<' {
+        return decode_sgr_mouse(bytes);
+    }
+    let end = match bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b)) {
+        Some(offset) => offset + 2,
+        None => return Decoded::Incomplete,
+    };
+    let used = end + 1;
+    let params: Vec<u32> = String::from_utf8_lossy(&bytes[2..end])
+        .split(';')
+        .map(|p| p.parse().unwrap_or(0))
+        .collect();
+    let modifiers = Modifiers::from_csi(params.get(1).copied().unwrap_or(1));
+    let k = match bytes[end] {
+        b'~' => match params[0] {
+            1 | 7 => Key::Home,
+            2 => Key::Insert,
+            3 => Key::Delete,
+            4 | 8 => Key::End,
+            5 => Key::PageUp,
+            6 => Key::PageDown,
+            11..=15 => Key::F((params[0] - 10) as u8),
+            17..=21 => Key::F((params[0] - 11) as u8),
+            23 | 24 => Key::F((params[0] - 12) as u8),
+            _ => return Decoded::Skip(used),
+        },
+        b'Z' => Key::BackTab,
+        c => match ss3_key(c) {
+            Some(k) => k,
+            None => return Decoded::Skip(used),
+        },
+    };
+    Decoded::Event(Event::Key(k, modifiers), used)
+}
+
+// ESC [ < button ; x ; y M for presses and motion, the same ending in m for releases
+fn decode_sgr_mouse(bytes: &[u8]) -> Decoded {
+    let end = match bytes[3..].iter().position(|b| *b == b'M' || *b == b'm') {
+        Some(offset) => offset + 3,
+        None if bytes[3..].iter().all(|b| b.is_ascii_digit() || *b == b';') => return Decoded::Incomplete,
+        None => return Decoded::Skip(3),
+    };
+    let used = end + 1;
+    let params: Vec<u16> = String::from_utf8_lossy(&bytes[3..end])
+        .split(';')
+        .filter_map(|p| p.parse().ok())
+        .collect();
+    if params.len() != 3 || params[1] == 0 || params[2] == 0 {
+        return Decoded::Skip(used);
+    }
+    let code = params[0];
+    let modifiers = Modifiers {
+        shift: code & 4 != 0,
+        alt: code & 8 != 0,
+        ctrl: code & 16 != 0,
+    };
+    let button = match code & 3 {
+        0 => MouseButton::Left,
+        1 => MouseButton::Middle,
+        _ => MouseButton::Right,
+    };
+    let kind = if code & 64 != 0 {
+        match code & 3 {
+            0 => MouseKind::ScrollUp,
+            1 => MouseKind::ScrollDown,
+            _ => return Decoded::Skip(used),
+        }
+    } else if code & 32 != 0 {
+        // motion without any button held is only reported in any-event (1003) mode
+        if code & 3 == 3 {
+            MouseKind::Move
+        } else {
+            MouseKind::Drag(button)
+        }
+    } else if bytes[end] == b'm' {
+        MouseKind::Release(button)
+    } else {
+        MouseKind::Press(button)
+    };
+    Decoded::Event(
+        Event::Mouse(Mouse {
+            kind,
+            x: params[1] - 1,
+            y: params[2] - 1,
+            modifiers,
+        }),
+        used,
+    )
+}
+
+fn decode_paste(bytes: &[u8]) -> Decoded {
+    let content = &bytes[PASTE_START.len()..];
+    match content.windows(PASTE_END.len()).position(|w| w == PASTE_END) {
+        Some(end) => Decoded::Event(
+            Event::Paste(String::from_utf8_lossy(&content[..end]).into_owned()),
+            PASTE_START.len() + end + PASTE_END.len(),
+        ),
+        None => Decoded::Incomplete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(key: Key) -> Event {
+        Event::Key(key, Modifiers::NONE)
+    }
+
+    fn decode_all(bytes: &[u8]) -> Vec<Event> {
+        Decoder::new().feed_at(bytes, Instant::now())
+    }
+
+    #[test]
+    fn plain_keys() {
+        assert_eq!(
+            decode_all(b"q\r\t\x7f"),
+            vec![plain(Key::Char('q')), plain(Key::Enter), plain(Key::Tab), plain(Key::Backspace)]
+        );
+    }
+
+    #[test]
+    fn ctrl_and_alt() {
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::NONE
+        };
+        let alt = Modifiers {
+            alt: true,
+            ..Modifiers::NONE
+        };
+        assert_eq!(decode_all(b"\x03"), vec![Event::Key(Key::Char('c'), ctrl)]);
+        assert_eq!(decode_all(b"\x1bx"), vec![Event::Key(Key::Char('x'), alt)]);
+    }
+
+    #[test]
+    fn csi_keys_with_modifiers() {
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::NONE
+        };
+        let ctrl_shift = Modifiers {
+            shift: true,
+            ctrl: true,
+            ..Modifiers::NONE
+        };
+        assert_eq!(decode_all(b"\x1b[A\x1bOB"), vec![plain(Key::Up), plain(Key::Down)]);
+        assert_eq!(decode_all(b"\x1b[1;2C"), vec![Event::Key(Key::Right, shift)]);
+        assert_eq!(decode_all(b"\x1b[5;6~"), vec![Event::Key(Key::PageUp, ctrl_shift)]);
+        assert_eq!(decode_all(b"\x1b[15~\x1b[Z"), vec![plain(Key::F(5)), plain(Key::BackTab)]);
+    }
+
+    #[test]
+    fn split_csi_waits_for_the_rest() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed_at(b"\x1b", start), vec![]);
+        assert_eq!(decoder.feed_at(b"", start + Duration::from_millis(10)), vec![]);
+        assert_eq!(decoder.feed_at(b"[", start + Duration::from_millis(20)), vec![]);
+        assert_eq!(decoder.feed_at(b"A", start + Duration::from_millis(30)), vec![plain(Key::Up)]);
+    }
+
+    #[test]
+    fn lone_escape_after_the_timeout() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed_at(b"\x1b", start), vec![]);
+        assert_eq!(decoder.feed_at(b"", start + ESC_TIMEOUT / 2), vec![]);
+        assert_eq!(decoder.feed_at(b"", start + ESC_TIMEOUT), vec![plain(Key::Esc)]);
+        assert_eq!(decoder.feed_at(b"", start + 2 * ESC_TIMEOUT), vec![]);
+    }
+
+    #[test]
+    fn timeout_counts_from_the_arrival_of_the_escape() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        // the escape arrives after a key, late in a long quiet period
+        assert_eq!(decoder.feed_at(b"a", start), vec![plain(Key::Char('a'))]);
+        let escape = start + 3 * ESC_TIMEOUT;
+        assert_eq!(decoder.feed_at(b"\x1b[", escape), vec![]);
+        assert_eq!(decoder.feed_at(b"", escape + ESC_TIMEOUT / 2), vec![]);
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::NONE
+        };
+        assert_eq!(decoder.feed_at(b"1;5D", escape + ESC_TIMEOUT / 2), vec![Event::Key(Key::Left, ctrl)]);
+    }
+
+    #[test]
+    fn expired_sequence_becomes_keys() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed_at(b"\x1b[", start), vec![]);
+        assert_eq!(
+            decoder.feed_at(b"", start + ESC_TIMEOUT),
+            vec![plain(Key::Esc), plain(Key::Char('['))]
+        );
+    }
+
+    #[test]
+    fn sgr_mouse() {
+        assert_eq!(
+            decode_all(b"\x1b[<0;10;5M\x1b[<0;10;5m\x1b[<64;1;1M\x1b[<35;3;4M\x1b[<32;3;4M"),
+            vec![
+                Event::Mouse(Mouse {
+                    kind: MouseKind::Press(MouseButton::Left),
+                    x: 9,
+                    y: 4,
+                    modifiers: Modifiers::NONE,
+                }),
+                Event::Mouse(Mouse {
+                    kind: MouseKind::Release(MouseButton::Left),
+                    x: 9,
+                    y: 4,
+                    modifiers: Modifiers::NONE,
+                }),
+                Event::Mouse(Mouse {
+                    kind: MouseKind::ScrollUp,
+                    x: 0,
+                    y: 0,
+                    modifiers: Modifiers::NONE,
+                }),
+                Event::Mouse(Mouse {
+                    kind: MouseKind::Move,
+                    x: 2,
+                    y: 3,
+                    modifiers: Modifiers::NONE,
+                }),
+                Event::Mouse(Mouse {
+                    kind: MouseKind::Drag(MouseButton::Left),
+                    x: 2,
+                    y: 3,
+                    modifiers: Modifiers::NONE,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_sgr_mouse_with_modifiers() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed_at(b"\x1b[<18;12", start), vec![]);
+        assert_eq!(
+            decoder.feed_at(b";7M", start + Duration::from_millis(20)),
+            vec![Event::Mouse(Mouse {
+                kind: MouseKind::Press(MouseButton::Right),
+                x: 11,
+                y: 6,
+                modifiers: Modifiers {
+                    ctrl: true,
+                    ..Modifiers::NONE
+                },
+            })]
+        );
+    }
+
+    #[test]
+    fn utf8_split_across_reads() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        let bytes = "é€".as_bytes();
+        assert_eq!(decoder.feed_at(&bytes[..1], start), vec![]);
+        assert_eq!(decoder.feed_at(&bytes[1..3], start), vec![plain(Key::Char('é'))]);
+        assert_eq!(decoder.feed_at(&bytes[3..], start), vec![plain(Key::Char('€'))]);
+    }
+
+    #[test]
+    fn paste_in_one_read() {
+        assert_eq!(
+            decode_all(b"\x1b[200~q [x]\x1b[201~z"),
+            vec![Event::Paste(String::from("q [x]")), plain(Key::Char('z'))]
+        );
     }
 
-    match char_buffer.get(0) {
-        Some(c) => Some(*c as char), 
-        None => None
+    #[test]
+    fn split_paste_is_never_flushed() {
+        let start = Instant::now();
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed_at(b"\x1b[200~roots 1 ", start), vec![]);
+        assert_eq!(decoder.feed_at(b"", start + 10 * ESC_TIMEOUT), vec![]);
+        assert_eq!(decoder.feed_at(b"-1 q", start + 11 * ESC_TIMEOUT), vec![]);
+        assert_eq!(decoder.feed_at(b"", start + 20 * ESC_TIMEOUT), vec![]);
+        assert_eq!(
+            decoder.feed_at(b"\x1b[201~", start + 21 * ESC_TIMEOUT),
+            vec![Event::Paste(String::from("roots 1 -1 q"))]
+        );
     }
 }
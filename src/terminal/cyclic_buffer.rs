@@ -1,3 +1,6 @@
+use crate::error::{Error, Result};
+use crate::fractal::Escape;
+
 pub struct Buffer<T> where 
 T: Copy
 {
@@ -19,38 +22,38 @@ T: Copy
             contents.push(row);
         }
         Buffer {
-            size: size, 
+            size, 
             pointers: (0, 0), 
-            contents: contents,
+            contents,
         }
     }
     pub fn clear(&mut self, item: T) {
         for y in 0..self.size.1 {
             for x in 0..self.size.0 {
-                if let Err(_) = self.put(item, x, y) {
+                if self.put(item, x, y).is_err() {
                     panic!("Could not clear screen");
                 }
             }
         }
     }
-    pub fn get(&self, x: u16, y: u16) -> Result<T, &'static str> {
+    pub fn get(&self, x: u16, y: u16) -> Result<T> {
         if x < self.size.0 && y < self.size.1 {
             let new_x = (x + self.pointers.0) % self.size.0;
             let new_y = (y + self.pointers.1) % self.size.1;
             return Ok(*self.contents.get(new_y as usize).unwrap().get(new_x as usize).unwrap());
         }
-        Err("x and y should be in the size of the buffer")
+        Err(Error::OutOfBounds { x, y, size: self.size })
     }
-    fn put_item(&mut self, item: T, x_buffer: u16, y_buffer: u16) -> Result<(), &'static str> { // puts an item directly into the buffer
+    fn put_item(&mut self, item: T, x_buffer: u16, y_buffer: u16) -> Result<()> { // puts an item directly into the buffer
         if x_buffer >= self.size.0 || y_buffer >= self.size.1 {
-            return Err("cannot put item in coordinate not in buffer");
+            return Err(Error::OutOfBounds { x: x_buffer, y: y_buffer, size: self.size });
         } 
         let row = &mut self.contents[y_buffer as usize];
         let cell = &mut row[x_buffer as usize];
         *cell = item;
         Ok(())
     }
-    pub fn shift(&mut self, direction: Direction, times: u16, placeholder_item: T) -> Result<(), &'static str> {
+    pub fn shift(&mut self, direction: Direction, times: u16, placeholder_item: T) -> Result<()> {
         // if a pointer jumps over a row or column, it needs to be filled with placeholders 
         match direction {
             Direction::Down => {
@@ -96,9 +99,9 @@ T: Copy
         }
         Ok(())
     }
-    pub fn put(&mut self, item: T, x_screen: u16, y_screen: u16) -> Result<(), &'static str> { // puts an item directly into the buffer
+    pub fn put(&mut self, item: T, x_screen: u16, y_screen: u16) -> Result<()> { // puts an item directly into the buffer
         if x_screen >= self.size.0 || y_screen >= self.size.1 {
-            return Err("cannot put item in coordinate not in buffer");
+            return Err(Error::OutOfBounds { x: x_screen, y: y_screen, size: self.size });
         } 
         let row = &mut self.contents[((y_screen + self.pointers.1) % self.size.1) as usize];
         let cell = &mut row[((x_screen + self.pointers.0) % self.size.0) as usize];
@@ -111,8 +114,21 @@ T: Copy
 pub enum Pixel where 
 {
     Recompute, // a render value means we have to re-compute the pixel
-    Value(char), // means we have a correct value in the buffer, no need to re-compute it
-    StatBar(char) // means it is part of the status bar, and should be re-rendered after moving
+    Value(Escape), // means we have a correct value in the buffer, no need to re-compute it
+    Stale(Escape), // an outdated value that is still drawn until the render replaces it
+}
+
+impl Pixel {
+    // the value to draw, current or not
+    pub fn escape(&self) -> Option<Escape> {
+        match self {
+            Pixel::Recompute => None,
+            Pixel::Value(escape) | Pixel::Stale(escape) => Some(*escape),
+        }
+    }
+    pub fn needs_render(&self) -> bool {
+        !matches!(self, Pixel::Value(_))
+    }
 }
 
 
@@ -0,0 +1,375 @@
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::palette::Rgb;
+
+// how long to wait for the terminal to answer the capability queries
+const QUERY_TIMEOUT: Duration = Duration::from_millis(300);
+const KITTY_QUERY_ID: u32 = 31;
+const KITTY_IMAGE_ID: u32 = 1;
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Sixel,
+}
+
+impl GraphicsProtocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            GraphicsProtocol::Kitty => "kitty",
+            GraphicsProtocol::Sixel => "sixel",
+        }
+    }
+}
+
+// what the terminal reported about itself during the handshake
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Capabilities {
+    pub kitty: bool,
+    pub sixel: bool,
+    pub window_pixels: Option<(u16, u16)>, // width, height
+}
+
+impl Capabilities {
+    // kitty is preferred since it transmits true colour and scales the image by itself
+    pub fn protocol(&self) -> Option<GraphicsProtocol> {
+        if self.kitty {
+            Some(GraphicsProtocol::Kitty)
+        } else if self.sixel {
+            Some(GraphicsProtocol::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+// asks the terminal for kitty graphics support, its window size in pixels and its device attributes.
+// every terminal answers the device attributes query, so its reply marks the end of the handshake
+pub fn detect<R: Read, W: Write>(stdin: &mut R, stdout: &mut W) -> io::Result<Capabilities> {
+    write!(
+        stdout,
+        "\x1b_Gi={},s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[14t\x1b[c",
+        KITTY_QUERY_ID
+    )?;
+    stdout.flush()?;
+
+    let start = Instant::now();
+    let mut response: Vec<u8> = Vec::new();
+    while start.elapsed() < QUERY_TIMEOUT {
+        let mut chunk = Vec::new();
+        stdin.read_to_end(&mut chunk)?;
+        response.extend_from_slice(&chunk);
+        if device_attributes(&response).is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(parse_responses(&response))
+}
+
+pub fn parse_responses(response: &[u8]) -> Capabilities {
+    let text = String::from_utf8_lossy(response);
+    let kitty_ok = format!("\x1b_Gi={};OK", KITTY_QUERY_ID);
+    Capabilities {
+        kitty: text.contains(&kitty_ok),
+        sixel: device_attributes(response).is_some_and(|attributes| attributes.contains(&4)),
+        window_pixels: window_pixels(&text),
+    }
+}
+
+// the parameters of a primary device attributes reply, ESC [ ? a ; b ; ... c
+fn device_attributes(response: &[u8]) -> Option<Vec<u32>> {
+    let text = String::from_utf8_lossy(response);
+    let start = text.find("\x1b[?")? + 3;
+    let end = start + text[start..].find('c')?;
+    Some(text[start..end].split(';').filter_map(|p| p.parse().ok()).collect())
+}
+
+// the reply to the window size query, ESC [ 4 ; height ; width t
+fn window_pixels(text: &str) -> Option<(u16, u16)> {
+    let start = text.find("\x1b[4;")? + 4;
+    let end = start + text[start..].find('t')?;
+    let mut params = text[start..end].split(';').map(|p| p.parse::<u16>());
+    let height = params.next()?.ok()?;
+    let width = params.next()?.ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+// a bitmap in row major order
+pub struct Image {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: u16, height: u16, background: Rgb) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+    pub fn put(&mut self, x: u16, y: u16, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y as usize * self.width as usize + x as usize] = color;
+        }
+    }
+    pub fn get(&self, x: u16, y: u16) -> Rgb {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+}
+
+// transmits the image and places it over `cells` terminal cells starting at the cursor, which is not moved.
+// the image sits below the text layer, so the status bar can be drawn over it
+pub fn write_kitty<W: Write>(out: &mut W, image: &Image, cells: (u16, u16)) -> io::Result<()> {
+    let mut data = Vec::with_capacity(image.pixels.len() * 3);
+    for pixel in image.pixels.iter() {
+        data.extend_from_slice(&[pixel.0, pixel.1, pixel.2]);
+    }
+    let encoded = base64(&data);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+
+    // drop the previous frame so the terminal does not keep every image around
+    write!(out, "\x1b_Ga=d,d=I,i={},q=2\x1b\\", KITTY_IMAGE_ID)?;
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = if index + 1 < chunks.len() { 1 } else { 0 };
+        if index == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},i={},z=-1,C=1,q=2,m={};",
+                image.width, image.height, cells.0, cells.1, KITTY_IMAGE_ID, more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+// writes the image as sixels, each image pixel is drawn as a `zoom` x `zoom` square.
+// colours are reduced to the 6x6x6 cube, and only the ones that are used get registered
+pub fn write_sixel<W: Write>(out: &mut W, image: &Image, zoom: u16) -> io::Result<()> {
+    let zoom = std::cmp::max(zoom, 1) as usize;
+    let width = image.width as usize * zoom;
+    let height = image.height as usize * zoom;
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let mut indices = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let color = image.get((x / zoom) as u16, (y / zoom) as u16);
+            indices.push(36 * level(color.0) + 6 * level(color.1) + level(color.2));
+        }
+    }
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    let mut registered = [false; 216];
+    for index in indices.iter() {
+        registered[*index] = true;
+    }
+    for (index, used) in registered.iter().enumerate() {
+        if *used {
+            let percent = |l: usize| l * 100 / 5;
+            write!(out, "#{};2;{};{};{}", index, percent(index / 36), percent(index / 6 % 6), percent(index % 6))?;
+        }
+    }
+
+    let mut row = vec![0usize; width];
+    for band in (0..height).step_by(6) {
+        let band_rows = &indices[band * width..std::cmp::min(band + 6, height) * width];
+        let mut colors_in_band = [false; 216];
+        for index in band_rows.iter() {
+            colors_in_band[*index] = true;
+        }
+        for (color, present) in colors_in_band.iter().enumerate() {
+            if !present {
+                continue;
+            }
+            for (x, bits) in row.iter_mut().enumerate() {
+                *bits = 0;
+                for (dy, line) in band_rows.chunks(width).enumerate() {
+                    if line[x] == color {
+                        *bits |= 1 << dy;
+                    }
+                }
+            }
+            write!(out, "#{}", color)?;
+            write_sixel_run_lengths(out, &row)?;
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")
+}
+
+fn write_sixel_run_lengths<W: Write>(out: &mut W, row: &[usize]) -> io::Result<()> {
+    let mut x = 0;
+    while x < row.len() {
+        let mut run = 1;
+        while x + run < row.len() && row[x + run] == row[x] {
+            run += 1;
+        }
+        let sixel = (63 + row[x] as u8) as char;
+        if run > 3 {
+            write!(out, "!{}{}", run, sixel)?;
+        } else {
+            for _ in 0..run {
+                write!(out, "{}", sixel)?;
+            }
+        }
+        x += run;
+    }
+    Ok(())
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let triple = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kitty_reply_is_recognised() {
+        let capabilities = parse_responses(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;22c");
+        assert!(capabilities.kitty);
+        assert!(!capabilities.sixel);
+        assert_eq!(capabilities.protocol(), Some(GraphicsProtocol::Kitty));
+    }
+
+    #[test]
+    fn kitty_error_or_other_id_is_not_support() {
+        assert!(!parse_responses(b"\x1b_Gi=31;ENOTSUPPORTED\x1b\\\x1b[?62c").kitty);
+        assert!(!parse_responses(b"\x1b_Gi=7;OK\x1b\\\x1b[?62c").kitty);
+    }
+
+    #[test]
+    fn sixel_needs_attribute_4() {
+        assert!(parse_responses(b"\x1b[?62;4;22c").sixel);
+        assert!(parse_responses(b"\x1b[?64;22;4c").sixel);
+        assert!(!parse_responses(b"\x1b[?62;22c").sixel);
+        assert!(!parse_responses(b"\x1b[?62;14;24c").sixel);
+        assert_eq!(parse_responses(b"\x1b[?62;4c").protocol(), Some(GraphicsProtocol::Sixel));
+        assert_eq!(parse_responses(b"").protocol(), None);
+    }
+
+    #[test]
+    fn window_size_is_height_then_width() {
+        let capabilities = parse_responses(b"\x1b[4;600;800t\x1b[?62c");
+        assert_eq!(capabilities.window_pixels, Some((800, 600)));
+        assert_eq!(parse_responses(b"\x1b[4;0;800t").window_pixels, None);
+        assert_eq!(parse_responses(b"\x1b[4;600t").window_pixels, None);
+    }
+
+    #[test]
+    fn detect_writes_the_queries_and_reads_the_replies() {
+        let mut stdin: &[u8] = b"\x1b_Gi=31;OK\x1b\\\x1b[4;480;640t\x1b[?62;4c";
+        let mut stdout = Vec::new();
+        let capabilities = detect(&mut stdin, &mut stdout).unwrap();
+        assert_eq!(stdout, b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[14t\x1b[c");
+        assert_eq!(
+            capabilities,
+            Capabilities {
+                kitty: true,
+                sixel: true,
+                window_pixels: Some((640, 480)),
+            }
+        );
+    }
+
+    #[test]
+    fn base64_pads_to_whole_groups() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xff, 0xfe]), "//4=");
+    }
+
+    #[test]
+    fn small_kitty_image_is_one_chunk() {
+        let mut image = Image::new(2, 1, Rgb(0, 0, 0));
+        image.put(1, 0, Rgb(255, 255, 255));
+        let mut out = Vec::new();
+        write_kitty(&mut out, &image, (3, 4)).unwrap();
+        let expected = format!(
+            "\x1b_Ga=d,d=I,i=1,q=2\x1b\\\x1b_Ga=T,f=24,s=2,v=1,c=3,r=4,i=1,z=-1,C=1,q=2,m=0;{}\x1b\\",
+            base64(&[0, 0, 0, 255, 255, 255])
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn large_kitty_image_is_chunked() {
+        // 1056 pixels are 3168 bytes, which base64 turns into 4224 characters, more than one chunk
+        let image = Image::new(33, 32, Rgb(1, 2, 3));
+        let mut out = Vec::new();
+        write_kitty(&mut out, &image, (10, 5)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let commands: Vec<&str> = text.split("\x1b\\").filter(|command| !command.is_empty()).collect();
+        assert_eq!(commands.len(), 3);
+        assert!(commands[1].starts_with("\x1b_Ga=T,f=24,s=33,v=32,c=10,r=5,i=1,z=-1,C=1,q=2,m=1;"));
+        assert!(commands[2].starts_with("\x1b_Gm=0;"));
+        let first = &commands[1][commands[1].find(';').unwrap() + 1..];
+        let second = &commands[2]["\x1b_Gm=0;".len()..];
+        assert_eq!(first.len(), KITTY_CHUNK_SIZE);
+        assert_eq!(format!("{}{}", first, second), base64(&[1, 2, 3].repeat(33 * 32)));
+    }
+
+    #[test]
+    fn sixel_registers_used_colours_and_repeats_runs() {
+        let image = Image::new(5, 2, Rgb(255, 0, 0));
+        let mut out = Vec::new();
+        write_sixel(&mut out, &image, 1).unwrap();
+        // red is 5 * 36 in the colour cube, both rows are set in every column
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1bPq\"1;1;5;2#180;2;100;0;0#180!5B$-\x1b\\");
+    }
+
+    #[test]
+    fn sixel_bands_are_six_rows_high() {
+        let mut image = Image::new(2, 7, Rgb(0, 0, 0));
+        image.put(0, 6, Rgb(255, 255, 255));
+        image.put(1, 6, Rgb(255, 255, 255));
+        let mut out = Vec::new();
+        write_sixel(&mut out, &image, 1).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1bPq\"1;1;2;7#0;2;0;0;0#215;2;100;100;100#0~~$-#215@@$-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn sixel_zoom_repeats_pixels() {
+        let mut image = Image::new(2, 1, Rgb(0, 0, 0));
+        image.put(1, 0, Rgb(255, 255, 255));
+        let mut out = Vec::new();
+        write_sixel(&mut out, &image, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1bPq\"1;1;4;2#0;2;0;0;0#215;2;100;100;100#0BB??$#215??BB$-\x1b\\"
+        );
+    }
+}
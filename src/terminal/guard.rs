@@ -0,0 +1,118 @@
+use std::io::{self, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use signal_hook::consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+use signal_hook::iterator::Signals;
+
+use crate::pool;
+
+// terminal modes the program relies on: the alternate screen so the scrollback survives,
+// a hidden cursor, bracketed paste, and mouse presses, drags and the sgr encoding
+const ENTER_MODES: &str = "\x1b[?1049h\x1b[?25l\x1b[?2004h\x1b[?1000h\x1b[?1002h\x1b[?1003h\x1b[?1006h";
+const LEAVE_MODES: &str = "\x1b[?1006l\x1b[?1003l\x1b[?1002l\x1b[?1000l\x1b[?2004l\x1b[0m\x1b[?25h\x1b[?1049l";
+
+// the terminal attributes from before raw mode, None while the terminal is not taken over
+static ORIGINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+// set when the program is continued after a suspend, so the screen gets repainted
+static RESUMED: AtomicBool = AtomicBool::new(false);
+
+// puts the terminal in raw mode on the alternate screen, and makes sure it is put back
+// when the guard is dropped, on a panic, and on SIGINT, SIGTERM, SIGHUP and SIGTSTP
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    pub fn install() -> io::Result<TerminalGuard> {
+        enter()?;
+
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // the hook runs before a panic is caught, and the pool catches those of its workers while the program goes on
+            if !pool::is_worker() {
+                restore();
+            }
+            default_hook(info);
+        }));
+
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP, SIGTSTP, SIGCONT])?;
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                match signal {
+                    SIGTSTP => {
+                        restore();
+                        // stops the process here, and returns once it has been continued
+                        let _ = signal_hook::low_level::emulate_default_handler(SIGTSTP);
+                        resume();
+                    }
+                    SIGCONT => resume(),
+                    _ => {
+                        restore();
+                        std::process::exit(128 + signal);
+                    }
+                }
+            }
+        });
+        Ok(TerminalGuard { _private: () })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+// true once after the program has been continued from a suspend
+pub fn take_resumed() -> bool {
+    RESUMED.swap(false, Ordering::Relaxed)
+}
+
+// stops the program like the default Ctrl-Z would, raw mode turns the key into a plain byte
+pub fn suspend() {
+    let _ = signal_hook::low_level::raise(SIGTSTP);
+}
+
+fn enter() -> io::Result<()> {
+    let mut original = ORIGINAL.lock().unwrap_or_else(|e| e.into_inner());
+    if original.is_some() {
+        return Ok(());
+    }
+    unsafe {
+        let mut attributes: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDOUT_FILENO, &mut attributes) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = attributes;
+        libc::cfmakeraw(&mut raw);
+        if libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &raw) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        *original = Some(attributes);
+    }
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", ENTER_MODES)?;
+    stdout.flush()
+}
+
+// puts the terminal back the way it was, safe to call any number of times
+pub fn restore() {
+    let mut original = ORIGINAL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(attributes) = original.take() {
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "{}", LEAVE_MODES);
+        let _ = stdout.flush();
+        unsafe {
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &attributes);
+        }
+    }
+}
+
+fn resume() {
+    if enter().is_ok() {
+        RESUMED.store(true, Ordering::Relaxed);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod async_input;
 pub mod cyclic_buffer;
+pub mod graphics;
+pub mod guard;
+pub mod prompt;
+pub mod render_mode;
 pub mod screen;
@@ -0,0 +1,48 @@
+use crate::terminal::async_input::{Event, Key};
+
+// a line of text typed into the status bar, used to edit fractals that are defined by text
+pub struct Prompt {
+    pub label: &'static str,
+    pub text: String,
+    pub error: Option<(usize, String)>, // column and message of the last rejected text
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PromptEvent {
+    Editing,
+    Submit,
+    Cancel,
+}
+
+impl Prompt {
+    pub fn new(label: &'static str, text: String) -> Prompt {
+        Prompt { label, text, error: None }
+    }
+    // keys edit the text at its end, there is no cursor to move around
+    pub fn handle(&mut self, event: &Event) -> PromptEvent {
+        match event {
+            Event::Key(Key::Enter, _) => return PromptEvent::Submit,
+            Event::Key(Key::Esc, _) => return PromptEvent::Cancel,
+            Event::Key(Key::Char('c'), modifiers) if modifiers.ctrl => return PromptEvent::Cancel,
+            Event::Key(Key::Backspace, _) => {
+                self.text.pop();
+            }
+            Event::Key(Key::Char(c), modifiers) if !modifiers.ctrl && !modifiers.alt => self.text.push(*c),
+            Event::Paste(text) => self.text.extend(text.chars().filter(|c| !c.is_control())),
+            _ => return PromptEvent::Editing,
+        }
+        self.error = None;
+        PromptEvent::Editing
+    }
+    // the text with a cursor after it, and a caret under the column of the error if there is one
+    pub fn lines(&self) -> Vec<String> {
+        let prefix = format!("{}: ", self.label);
+        let mut lines = vec![format!("{}{}_", prefix, self.text)];
+        if let Some((column, message)) = &self.error {
+            let indent = prefix.chars().count() + column.saturating_sub(1);
+            lines.push(format!("{}^", " ".repeat(indent)));
+            lines.push(format!("error at column {}: {}", column, message));
+        }
+        lines
+    }
+}
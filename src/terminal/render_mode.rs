@@ -0,0 +1,78 @@
+use crate::terminal::graphics::GraphicsProtocol;
+
+// how many image pixels one sample covers in the graphics mode, full resolution is too slow to compute
+pub const PIXELS_PER_SAMPLE: u16 = 2;
+
+// how buffer samples are packed into terminal cells
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RenderMode {
+    Characters, // one sample per cell, drawn with the shading glyphs
+    HalfBlock,  // two samples stacked in a cell, drawn as the foreground and background of '▀'
+    Braille,    // a 2x4 grid of samples per cell, drawn as the dots of a braille character
+    // a real bitmap sent through a terminal graphics protocol, cell_pixels is the size of a cell in pixels
+    Graphics {
+        protocol: GraphicsProtocol,
+        cell_pixels: (u16, u16),
+    },
+}
+
+pub const UPPER_HALF_BLOCK: char = '▀';
+pub const LOWER_HALF_BLOCK: char = '▄';
+pub const FULL_BLOCK: char = '█';
+const BRAILLE_BLANK: u32 = 0x2800;
+// bit of each dot in a braille character, indexed by [x][y] inside the cell
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+// the braille character with a dot for every set sample, indexed by [x][y] inside the cell
+pub fn braille(dots: [[bool; 4]; 2]) -> char {
+    let mut code = BRAILLE_BLANK;
+    for (x, column) in dots.iter().enumerate() {
+        for (y, dot) in column.iter().enumerate() {
+            if *dot {
+                code |= BRAILLE_DOTS[x][y];
+            }
+        }
+    }
+    std::char::from_u32(code).unwrap_or(' ')
+}
+
+impl RenderMode {
+    // number of samples in a terminal cell, horizontally and vertically
+    pub fn samples_per_cell(self) -> (u16, u16) {
+        match self {
+            RenderMode::Characters => (1, 1),
+            RenderMode::HalfBlock => (1, 2),
+            RenderMode::Braille => (2, 4),
+            RenderMode::Graphics { cell_pixels, .. } => (
+                std::cmp::max(1, cell_pixels.0 / PIXELS_PER_SAMPLE),
+                std::cmp::max(1, cell_pixels.1 / PIXELS_PER_SAMPLE),
+            ),
+        }
+    }
+    // graphics is only offered if the terminal said it supports it
+    pub fn next(self, graphics: Option<RenderMode>) -> RenderMode {
+        match self {
+            RenderMode::Characters => RenderMode::HalfBlock,
+            RenderMode::HalfBlock => RenderMode::Braille,
+            RenderMode::Braille => graphics.unwrap_or(RenderMode::Characters),
+            RenderMode::Graphics { .. } => RenderMode::Characters,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderMode::Characters => "characters",
+            RenderMode::HalfBlock => "half blocks",
+            RenderMode::Braille => "braille",
+            RenderMode::Graphics { protocol, .. } => protocol.name(),
+        }
+    }
+    // ratio between the height and width of a sample, text cells are assumed to be about twice as tall as wide
+    pub fn sample_aspect(self) -> f64 {
+        let (sx, sy) = self.samples_per_cell();
+        let cell_aspect = match self {
+            RenderMode::Graphics { cell_pixels, .. } => cell_pixels.1 as f64 / cell_pixels.0 as f64,
+            _ => 2.0,
+        };
+        cell_aspect * sx as f64 / sy as f64
+    }
+}
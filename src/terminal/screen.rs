@@ -1,166 +1,486 @@
 extern crate num;
 use num::complex::Complex;
 
-use termion::raw::IntoRawMode;
 use termion::async_stdin;
 use std::io::{Write, stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use crate::terminal::cyclic_buffer::{Buffer, Direction, Pixel};
-use crate::mandelbrot;
+use crate::error::{Error, Result};
+use crate::terminal::async_input::Decoder;
+use crate::terminal::graphics::{self, GraphicsProtocol, Image};
+use crate::terminal::guard::{self, TerminalGuard};
+use crate::terminal::render_mode::{self, RenderMode};
+use crate::fractal::Escape;
+use crate::palette::{ColorDepth, Palette, Rgb, PALETTES};
+use crate::fractal::DefaultView;
+use crate::render::{Grid, PerturbationState};
+use crate::terminal::prompt::Prompt;
+use crate::viewport::Viewport;
+use crate::shading::Shading;
+
 
 fn in_range<T>(x: T, lower: T, upper: T) -> bool where 
 T: PartialOrd 
 {
-    return lower <= x && x < upper;
+    lower <= x && x < upper
 }
 
 pub struct Screen {
     pub stdin: termion::AsyncReader, 
-    pub stdout: termion::raw::RawTerminal<std::io::Stdout>, 
+    pub input: Decoder, // keeps partial escape sequences between reads of stdin
+    pub stdout: std::io::Stdout, 
+    _guard: TerminalGuard, // restores the terminal when the screen goes away
     pub term_size: (u16, u16), 
-    pub scale: f64, 
-    scale_change: f64, // how much the scale has changed since the last zoom
-    pub center: Complex<f64>,
+    pub sample_size: (u16, u16), // size of the sample grid, term_size times the samples per cell
+    pub viewport: Viewport, // where the sample grid is in the complex plane
     pub buffer: Buffer<Pixel>,
-    vertical_scaling_constant: f64
+    pub shading: Shading,
+    pub smooth: bool, // colour and shade by the normalized iteration count instead of the integer one
+    pub palette: usize, // index into palette::PALETTES
+    pub color_depth: ColorDepth,
+    pub render_mode: RenderMode,
+    graphics: Option<RenderMode>, // the bitmap render mode, if the terminal supports one
+    pub stat_bar: Vec<String>, // lines of the status box, drawn over the fractal
+    pub prompt: Option<Prompt>, // text being typed into the status bar
+    pub perturbation: PerturbationState, // the reference orbit of deep zooms, reused while the view stays near it
+    pub inset: Option<(Grid, u16)>, // a small preview in the bottom right corner, with the iterations it was computed with
+    resized: Arc<AtomicBool>, // set by the SIGWINCH handler
 }
 
 impl Screen {
-    pub fn new_screen() -> Screen {
-        let stdout = stdout(); 
-        let stdout = stdout.into_raw_mode().unwrap();
-        let stdin = async_stdin();
-        let (w, h) = termion::terminal_size().unwrap();
+    // view is the region of the plane that is shown first
+    pub fn new_screen(view: DefaultView) -> Result<Screen> {
+        let guard = TerminalGuard::install().map_err(Error::io("set up the terminal"))?;
+        let mut stdout = stdout(); 
+        let mut stdin = async_stdin();
+        let (w, h) = termion::terminal_size().map_err(Error::io("get the terminal size"))?;
+        let graphics = detect_graphics(&mut stdin, &mut stdout, (w, h));
+        let resized = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGWINCH, Arc::clone(&resized))
+            .map_err(Error::io("listen for terminal resizes"))?;
         let buffer: Buffer<Pixel> = Buffer::new((w, h), Pixel::Recompute);
+        let color_depth = ColorDepth::detect();
+        let palette = match color_depth {
+            ColorDepth::Monochrome => Palette::index_of("none"),
+            _ => Palette::index_of("classic"),
+        };
+
+        let render_mode = RenderMode::Characters;
 
-        Screen {
-            stdin: stdin, 
-            stdout: stdout,
+        let mut screen = Screen {
+            stdin, 
+            input: Decoder::new(),
+            stdout,
+            _guard: guard,
             term_size: (w, h),
-            scale: 0.02, 
-            scale_change: 1.0,
-            center: Complex::new(0.0, 0.0),
-            buffer: buffer, 
-            vertical_scaling_constant: 2.0
+            sample_size: (w, h),
+            viewport: Viewport::new(view.center, 1.0, render_mode.sample_aspect()),
+            buffer, 
+            shading: Shading::Binary,
+            smooth: true,
+            palette,
+            color_depth,
+            render_mode,
+            graphics,
+            stat_bar: Vec::new(),
+            prompt: None,
+            perturbation: PerturbationState::default(),
+            inset: None,
+            resized,
+        };
+        screen.fit_view(view);
+        Ok(screen)
+    }
+    // marks every sample for the next render, while the old values stay on screen until they are replaced
+    pub fn invalidate(&mut self) -> Result<()> {
+        let (w, h) = self.sample_size;
+        for y in 0..h {
+            for x in 0..w {
+                if let Pixel::Value(escape) = self.buffer.get(x, y)? {
+                    self.buffer.put(Pixel::Stale(escape), x, y)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    // shows the whole circle, with nothing in the buffer kept
+    pub fn fit_view(&mut self, view: DefaultView) {
+        let (w, h) = self.sample_size;
+        let scale = f64::max(2.0 * view.radius / w as f64, 2.0 * view.radius / (h as f64 * self.viewport.aspect));
+        self.viewport = Viewport::new(view.center, scale, self.viewport.aspect);
+        self.buffer.clear(Pixel::Recompute);
+    }
+    pub fn next_render_mode(&mut self) {
+        self.set_render_mode(self.render_mode.next(self.graphics));
+    }
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        // keep the width of a cell in the complex plane the same across modes
+        let old_samples = self.render_mode.samples_per_cell();
+        let new_samples = render_mode.samples_per_cell();
+        self.viewport.scale *= old_samples.0 as f64 / new_samples.0 as f64;
+        self.render_mode = render_mode;
+        self.viewport.aspect = render_mode.sample_aspect();
+        self.sample_size = (self.term_size.0 * new_samples.0, self.term_size.1 * new_samples.1);
+        self.buffer = Buffer::new(self.sample_size, Pixel::Recompute);
+    }
+    // checks whether the terminal has been resized since the last call, and adapts the buffer if it has.
+    // returns Some(exact) on a resize, where exact is false if some kept pixels are half a sample off
+    pub fn poll_resize(&mut self) -> Result<Option<bool>> {
+        if !self.resized.swap(false, Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let new_size = termion::terminal_size().map_err(Error::io("get the terminal size"))?;
+        if new_size == self.term_size {
+            return Ok(None);
+        }
+        self.on_resize(new_size).map(Some)
+    }
+    // rebuilds the buffer for a new terminal size, keeping the center and scale.
+    // pixels that are still on screen are kept, returns false if they had to be rounded to the nearest sample
+    pub fn on_resize(&mut self, term_size: (u16, u16)) -> Result<bool> {
+        let (sx, sy) = self.render_mode.samples_per_cell();
+        let (old_w, old_h) = self.sample_size;
+        let (w, h) = (term_size.0 * sx, term_size.1 * sy);
+        let mut buff: Buffer<Pixel> = Buffer::new((w, h), Pixel::Recompute);
+        // the center is at half the width, so a sample moves by half the change in size
+        let shift_x = (old_w as f64 - w as f64) / 2.0;
+        let shift_y = (old_h as f64 - h as f64) / 2.0;
+        let exact = shift_x.fract() == 0.0 && shift_y.fract() == 0.0;
+        for x in 0..w {
+            for y in 0..h {
+                let old_x = (x as f64 + shift_x).round() as i32;
+                let old_y = (y as f64 + shift_y).round() as i32;
+                if in_range(old_x, 0, old_w as i32) && in_range(old_y, 0, old_h as i32) {
+                    buff.put(self.buffer.get(old_x as u16, old_y as u16)?, x, y)?;
+                }
+            }
         }
+        self.term_size = term_size;
+        self.sample_size = (w, h);
+        self.buffer = buff;
+        Ok(exact)
     }
-    pub fn get_complex_coords(&self, x: u16, y: u16) -> Result<Complex<f64>, &'static str> {
-        if x < self.term_size.0 && y < self.term_size.1 {
-            let (w, h) = self.term_size;
-            let x_c = ((x as f64) - (w as f64)/2.0)*self.scale;
-            let y_c = -((y as f64) - (h as f64)/2.0)*self.scale*self.vertical_scaling_constant;
-            return Ok(self.center + Complex::new(x_c, y_c));
+    // takes coordinates in sample space, not in terminal cells
+    pub fn get_complex_coords(&self, x: u16, y: u16) -> Result<Complex<f64>> {
+        if x < self.sample_size.0 && y < self.sample_size.1 {
+            return Ok(self.viewport.point(x as f64, y as f64, self.sample_size));
         }
-        Err("specified screen coordinates not on screen")
+        Err(Error::OutOfBounds { x, y, size: self.sample_size })
     }
-    pub fn clear_screen(&mut self) -> Result<(), &'static str> {
-        let res = write!(self.stdout,
+    pub fn clear_screen(&mut self) -> Result<()> {
+        write!(self.stdout,
                "{}{}",
                termion::clear::All, 
-               termion::cursor::Goto(1, 1)); 
-        if let Err(_e) = res {
-            return Err("could not clear screen");
-        }
-        Ok(())
+               termion::cursor::Goto(1, 1))
+            .map_err(Error::io("clear the screen"))
     }
-    pub fn putchar(&mut self, x: u16, y: u16, c: Pixel) -> Result<(), &'static str>{
+    pub fn putchar(&mut self, x: u16, y: u16, c: Pixel) -> Result<()> {
         self.buffer.put(c, x, y)?;
         Ok(())
     }
-    pub fn flush_screen(&mut self) -> Result<(), &'static str> {
-        if let Err(_e) = self.stdout.flush() {
-            return Err("could not flush screen");
+    // true if the program was just continued after a suspend, the whole screen has to be drawn again.
+    // the terminal may have been resized in the meantime, so the size is checked on the next poll
+    pub fn poll_resume(&mut self) -> bool {
+        if guard::take_resumed() {
+            self.resized.store(true, Ordering::Relaxed);
+            return true;
         }
-        Ok(())
+        false
     }
-    pub fn render(&mut self) -> Result<(), &'static str> {
-        for x in 0..self.term_size.0 {
-            for y in 0..self.term_size.1 {
-                match self.buffer.get(x, y)? {
-                    Pixel::Recompute => {return Err("cannot render screen where some pixels are not computed");},
-                    Pixel::Value(c) | Pixel::StatBar(c) => {
-                        let res = write!(self.stdout,
-                               "{}{}",
-                               termion::cursor::Goto(x + 1, y + 1), 
-                               c);
-                        if let Err(_e) = res {
-                            return Err("could not write to screen during render");
-                        }
+    pub fn flush_screen(&mut self) -> Result<()> {
+        self.stdout.flush().map_err(Error::io("flush the screen"))
+    }
+    pub fn next_palette(&mut self) {
+        self.palette = (self.palette + 1) % PALETTES.len();
+    }
+    fn get_value(&self, x: u16, y: u16) -> Result<Escape> {
+        self.buffer.get(x, y)?.escape().ok_or(Error::UnrenderedPixel { x, y })
+    }
+    // false while a background render has not reached some sample of the cell yet
+    fn is_computed(&self, x: u16, y: u16) -> Result<bool> {
+        let (sx, sy) = self.render_mode.samples_per_cell();
+        for dx in 0..sx {
+            for dy in 0..sy {
+                if self.buffer.get(sx * x + dx, sy * y + dy)?.escape().is_none() {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+    // a single sample drawn as a shaded character on a coloured background
+    fn character(&self, escape: &Escape, n_iter: u16) -> String {
+        let background = match PALETTES[self.palette].color(escape, self.smooth) {
+            Some(color) => self.color_depth.background(color),
+            None => termion::color::Bg(termion::color::Reset).to_string(),
+        };
+        format!("{}{}", background, self.shading.glyph(escape, n_iter, self.smooth))
+    }
+    // the characters and colour escapes that draw the cell at (x, y) in terminal coordinates
+    fn cell(&self, x: u16, y: u16, n_iter: u16) -> Result<String> {
+        let palette = &PALETTES[self.palette];
+        let cell = match self.render_mode {
+            RenderMode::Characters => self.character(&self.get_value(x, y)?, n_iter),
+            RenderMode::HalfBlock => {
+                let upper = self.get_value(x, 2 * y)?;
+                let lower = self.get_value(x, 2 * y + 1)?;
+                match (palette.color(&upper, self.smooth), palette.color(&lower, self.smooth)) {
+                    (Some(upper), Some(lower)) if self.color_depth != ColorDepth::Monochrome => format!(
+                        "{}{}{}",
+                        self.color_depth.foreground(upper),
+                        self.color_depth.background(lower),
+                        render_mode::UPPER_HALF_BLOCK
+                    ),
+                    // without colours the two samples can still be told apart by which half is filled
+                    _ => {
+                        let glyph = match (upper.interior, lower.interior) {
+                            (true, true) => render_mode::FULL_BLOCK,
+                            (true, false) => render_mode::UPPER_HALF_BLOCK,
+                            (false, true) => render_mode::LOWER_HALF_BLOCK,
+                            (false, false) => ' ',
+                        };
+                        format!("{}{}", termion::style::Reset, glyph)
                     }
                 }
             }
-        } 
+            RenderMode::Braille => {
+                // monochrome, a dot is raised for every sample inside the set
+                let mut dots = [[false; 4]; 2];
+                for (dx, column) in dots.iter_mut().enumerate() {
+                    for (dy, dot) in column.iter_mut().enumerate() {
+                        *dot = self.get_value(2 * x + dx as u16, 4 * y + dy as u16)?.interior;
+                    }
+                }
+                format!("{}{}", termion::style::Reset, render_mode::braille(dots))
+            }
+            RenderMode::Graphics { .. } => return Err(Error::RenderState("graphics are not drawn cell by cell")),
+        };
+        Ok(cell)
+    }
+    pub fn render(&mut self, n_iter: u16) -> Result<()> {
+        if let RenderMode::Graphics { protocol, .. } = self.render_mode {
+            return self.render_graphics(protocol);
+        }
+        let mut frame = String::new();
+        for y in 0..self.term_size.1 {
+            frame.push_str(&termion::cursor::Goto(1, y + 1).to_string());
+            for x in 0..self.term_size.0 {
+                if self.is_computed(x, y)? {
+                    frame.push_str(&self.cell(x, y, n_iter)?);
+                } else {
+                    frame.push_str(&format!("{} ", termion::style::Reset));
+                }
+            }
+        }
+        frame.push_str(termion::style::Reset.as_ref());
+        frame.push_str(&self.stat_bar_overlay());
+        frame.push_str(&self.inset_overlay()?);
+        write!(self.stdout, "{}", frame).map_err(Error::io("write to the screen during render"))
+    }
+    // draws the sample grid as one bitmap, then the status bar as text on top of it
+    fn render_graphics(&mut self, protocol: GraphicsProtocol) -> Result<()> {
+        let palette = &PALETTES[self.palette];
+        let (w, h) = self.sample_size;
+        let rows = match protocol {
+            GraphicsProtocol::Kitty => self.term_size.1,
+            // a sixel image touching the last line scrolls the terminal, so that line is left out
+            GraphicsProtocol::Sixel => self.term_size.1.saturating_sub(1),
+        };
+        let height = std::cmp::min(h, rows * self.render_mode.samples_per_cell().1);
+        let mut image = Image::new(w, height, Rgb(0, 0, 0));
+        for y in 0..height {
+            for x in 0..w {
+                // samples the render has not reached yet stay black
+                let color = match self.buffer.get(x, y)?.escape() {
+                    None => Rgb(0, 0, 0),
+                    Some(escape) => palette.color(&escape, self.smooth).unwrap_or(if escape.interior {
+                        Rgb(0, 0, 0)
+                    } else {
+                        Rgb(255, 255, 255)
+                    }),
+                };
+                image.put(x, y, color);
+            }
+        }
+
+        let overlay = self.stat_bar_overlay() + &self.inset_overlay()?;
+        let mut frame: Vec<u8> = Vec::new();
+        write!(frame, "{}", termion::cursor::Goto(1, 1))
+            .and_then(|_| match protocol {
+                GraphicsProtocol::Kitty => graphics::write_kitty(&mut frame, &image, (self.term_size.0, rows)),
+                GraphicsProtocol::Sixel => graphics::write_sixel(&mut frame, &image, render_mode::PIXELS_PER_SAMPLE),
+            })
+            .map_err(Error::io("encode the graphics frame"))?;
+        write!(self.stdout, "{}", termion::style::Reset)
+            .and_then(|_| self.stdout.write_all(&frame))
+            .and_then(|_| write!(self.stdout, "{}", overlay))
+            .map_err(Error::io("write to the screen during render"))
+    }
+    // the status bar is a box in the top left, drawn on top of the already rendered cells
+    fn stat_bar_overlay(&self) -> String {
+        let max_width = match self.stat_bar.iter().map(|line| line.chars().count()).max() {
+            Some(width) => std::cmp::min(width, self.term_size.0.saturating_sub(2) as usize),
+            None => return String::new(),
+        };
+        let height = std::cmp::min(self.stat_bar.len(), self.term_size.1.saturating_sub(2) as usize);
+        let horizontal_bar = "-".repeat(max_width);
+        let mut overlay = format!("{} {} ", termion::cursor::Goto(1, 1), horizontal_bar);
+        for (index, line) in self.stat_bar.iter().take(height).enumerate() {
+            let line: String = line.chars().take(max_width).collect();
+            overlay.push_str(&format!(
+                "{}|{:<width$}|",
+                termion::cursor::Goto(1, index as u16 + 2),
+                line,
+                width = max_width
+            ));
+        }
+        overlay.push_str(&format!("{} {} ", termion::cursor::Goto(1, height as u16 + 2), horizontal_bar));
+        overlay
+    }
+    // cells inside the border of the inset, a quarter of the screen wide and about as tall as it is wide
+    pub fn inset_size(&self) -> (u16, u16) {
+        let (w, h) = self.term_size;
+        let width = (w / 4).saturating_sub(2);
+        let height = std::cmp::min(width / 2, (h / 2).saturating_sub(2));
+        (width, height)
+    }
+    // the inset is a box in the bottom right, drawn with one character per sample whatever the render mode
+    fn inset_overlay(&self) -> Result<String> {
+        let (grid, n_iter) = match &self.inset {
+            Some((grid, n_iter)) if grid.size.0 > 0 && grid.size.1 > 0 => (grid, *n_iter),
+            _ => return Ok(String::new()),
+        };
+        let (w, h) = grid.size;
+        let left = self.term_size.0.saturating_sub(w + 1);
+        let top = self.term_size.1.saturating_sub(h + 1);
+        let horizontal_bar = "-".repeat(w as usize);
+        let mut overlay = format!("{} {} ", termion::cursor::Goto(left, top), horizontal_bar);
+        for y in 0..h {
+            overlay.push_str(&format!("{}{}|", termion::style::Reset, termion::cursor::Goto(left, top + y + 1)));
+            for x in 0..w {
+                overlay.push_str(&self.character(&grid.get(x, y)?, n_iter));
+            }
+            overlay.push_str(&format!("{}|", termion::style::Reset));
+        }
+        overlay.push_str(&format!("{} {} ", termion::cursor::Goto(left, top + h + 1), horizontal_bar));
+        Ok(overlay)
+    }
+    // the nearest cell on screen, mouse events can be reported outside of it after a resize or while dragging
+    pub fn clamp_cell(&self, x: u16, y: u16) -> (u16, u16) {
+        (
+            std::cmp::min(x, self.term_size.0.saturating_sub(1)),
+            std::cmp::min(y, self.term_size.1.saturating_sub(1)),
+        )
+    }
+    // the sample in the middle of a terminal cell
+    pub fn cell_to_sample(&self, x: u16, y: u16) -> (u16, u16) {
+        let (sx, sy) = self.render_mode.samples_per_cell();
+        (x * sx + sx / 2, y * sy + sy / 2)
+    }
+    // moves the view so the given sample ends up in the middle, by whole samples so the buffer is reused
+    pub fn recenter_on(&mut self, x: u16, y: u16) -> Result<()> {
+        if x >= self.sample_size.0 || y >= self.sample_size.1 {
+            return Err(Error::OutOfBounds { x, y, size: self.sample_size });
+        }
+        let (dx, dy) = (x as i32 - (self.sample_size.0 / 2) as i32, y as i32 - (self.sample_size.1 / 2) as i32);
+        self.move_by_samples(dx, dy)
+    }
+    // positive dx moves the view right, positive dy moves it down
+    pub fn move_by_samples(&mut self, dx: i32, dy: i32) -> Result<()> {
+        if dx > 0 {
+            self.on_move(Direction::Left, dx as u16)?;
+        } else if dx < 0 {
+            self.on_move(Direction::Right, (-dx) as u16)?;
+        }
+        if dy > 0 {
+            self.on_move(Direction::Up, dy as u16)?;
+        } else if dy < 0 {
+            self.on_move(Direction::Down, (-dy) as u16)?;
+        }
         Ok(())
     }
-    pub fn on_move(&mut self, direction: Direction, times: u16) -> Result<(), &'static str>{
+    pub fn on_move(&mut self, direction: Direction, times: u16) -> Result<()> {
         self.buffer.shift(direction, times, Pixel::Recompute)?;
+        let times = times as f64;
         match direction {
-            Direction::Right => {
-                self.center += times as f64*Complex::new(-self.scale, 0.0);
-            },
-            Direction::Left => {
-                self.center += times as f64*Complex::new(self.scale, 0.0);
-            },
-            Direction::Up => {
-                self.center += times as f64*Complex::new(0.0, -self.scale*self.vertical_scaling_constant);
-            },
-            Direction::Down => {
-                self.center += times as f64*Complex::new(0.0, self.scale*self.vertical_scaling_constant);
-            },
+            Direction::Right => self.viewport.pan(-times, 0.0),
+            Direction::Left => self.viewport.pan(times, 0.0),
+            Direction::Up => self.viewport.pan(0.0, times),
+            Direction::Down => self.viewport.pan(0.0, -times),
         }
         Ok(())
     }
-    pub fn on_zoom(&mut self, zoom_amount: f64) -> Result<(), &'static str> { // if zoom_amount > 1 => zoom in, else => zoom out
-        self.scale_change /= zoom_amount;
-        // check if with this scale change you have to modify the screen 
-        if (1.0 - self.scale_change).abs()*(std::cmp::max(self.term_size.0, self.term_size.1) as f64) > 2.0 {
-            let (w, h) = self.term_size;
-            let mut buff: Buffer<Pixel> = Buffer::new((w, h), Pixel::Recompute); 
-            buff.pointers = self.buffer.pointers;
-            let neighborhood: [(i32, i32); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, 1), (0, -1), (1, -1), (1, 0), (1, 1)]; 
+    // the sample in the middle of the screen, zooming around it keeps the center fixed
+    pub fn middle(&self) -> (f64, f64) {
+        (self.sample_size.0 as f64 / 2.0, self.sample_size.1 as f64 / 2.0)
+    }
+    // if zoom_amount > 1 => zoom in, else => zoom out.
+    // anchor is a point in sample space whose complex coordinate stays where it is
+    pub fn on_zoom(&mut self, zoom_amount: f64, anchor: (f64, f64)) -> Result<()> {
+        let scale_change = 1.0 / zoom_amount;
+        let (ax, ay) = anchor;
+        let (w, h) = self.sample_size;
+        let mut buff: Buffer<Pixel> = Buffer::new((w, h), Pixel::Recompute); 
+        buff.pointers = self.buffer.pointers;
+        let neighborhood: [(i32, i32); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, 1), (0, -1), (1, -1), (1, 0), (1, 1)]; 
 
-            for x in 0..w {
-                for y in 0..h {
-                    let old_x = w as f64 / 2.0 + (x as f64 - w as f64 / 2.0) * self.scale_change;
-                    let old_y = h as f64 / 2.0 + (y as f64 - h as f64 / 2.0) * self.scale_change; 
-                    let old_x = old_x.round() as i32;
-                    let old_y = old_y.round() as i32;
+        for x in 0..w {
+            for y in 0..h {
+                // where the complex coordinate of (x, y) was before the zoom
+                let old_x = ax + (x as f64 - ax) * scale_change;
+                let old_y = ay + (y as f64 - ay) * scale_change;
+                let old_x = old_x.round() as i32;
+                let old_y = old_y.round() as i32;
 
-                    if (!in_range(old_x, 0, w as i32)) || (!in_range(old_y, 0, h as i32)) { continue; }
+                if (!in_range(old_x, 0, w as i32)) || (!in_range(old_y, 0, h as i32)) { continue; }
 
-                    let mut surely_in_fractal = true;
-                    let mut surely_outside_fractal = true;
-                    for cell in neighborhood.iter() {
-                        let coords = (cell.0 + old_x, cell.1 + old_y);
-                        if in_range(coords.0, 0, w as i32) && in_range(coords.1, 0, h as i32) {
-                            match self.buffer.get(coords.0 as u16, coords.1 as u16)? {
-                                Pixel::Value(mandelbrot::IN_FRACTAL) => { // cannot be sure it is outside of fractal
-                                    surely_outside_fractal = false;
-                                },
-                                Pixel::Value(mandelbrot::OUTSIDE_FRACTAL) => {
-                                    surely_in_fractal = false;
-                                }, 
-                                Pixel::StatBar(_) => {},
-                                _ => {return Err("there was an unrendered pixel on the screen somehow");}
-                            }
-                            if (!surely_in_fractal) && (!surely_outside_fractal) {
-                                break;
-                            }
+                let pixel = self.buffer.get(old_x as u16, old_y as u16)?;
+                let old = match pixel.escape() {
+                    Some(escape) => escape,
+                    None => continue,
+                };
+                // several zooms can be applied before the next render, so Recompute neighbours mean nothing is known
+                let mut same_region = true;
+                for cell in neighborhood.iter() {
+                    let coords = (cell.0 + old_x, cell.1 + old_y);
+                    if in_range(coords.0, 0, w as i32) && in_range(coords.1, 0, h as i32) {
+                        same_region = match self.buffer.get(coords.0 as u16, coords.1 as u16)?.escape() {
+                            Some(escape) => escape.same_region(&old),
+                            None => false,
+                        };
+                        if !same_region {
+                            break;
                         }
                     }
-                    if surely_outside_fractal {
-                        buff.put(Pixel::Value(mandelbrot::OUTSIDE_FRACTAL), x, y)?;
-                    } else if surely_in_fractal {
-                        buff.put(Pixel::Value(mandelbrot::IN_FRACTAL), x, y)?;
-                    }
                 }
-            } 
-            self.buffer = buff;
-            self.scale *= self.scale_change;
-            self.scale_change = 1.0;
-        }
+                // the whole neighbourhood agrees (all inside, all outside or all in one basin),
+                // so the old value is a good stand-in until the next full render
+                if same_region {
+                    buff.put(pixel, x, y)?;
+                }
+            }
+        } 
+        self.buffer = buff;
+        self.viewport.zoom(zoom_amount, anchor, self.sample_size);
         Ok(()) 
     }
 }
 
-
-
+// works out which bitmap render mode the terminal supports, if any.
+// cell sizes come from the terminal's answer, or from the kernel if the terminal did not say
+fn detect_graphics<R: std::io::Read, W: Write>(stdin: &mut R, stdout: &mut W, term_size: (u16, u16)) -> Option<RenderMode> {
+    let capabilities = graphics::detect(stdin, stdout).ok()?;
+    let protocol = capabilities.protocol()?;
+    let (pixel_w, pixel_h) = match capabilities.window_pixels {
+        Some(pixels) => pixels,
+        None => termion::terminal_size_pixels().ok()?,
+    };
+    if term_size.0 == 0 || term_size.1 == 0 || pixel_w < term_size.0 || pixel_h < term_size.1 {
+        return None;
+    }
+    Some(RenderMode::Graphics {
+        protocol,
+        cell_pixels: (pixel_w / term_size.0, pixel_h / term_size.1),
+    })
+}
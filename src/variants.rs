@@ -0,0 +1,110 @@
+extern crate num;
+use num::complex::Complex;
+use std::sync::Arc;
+
+use crate::fractal::{DefaultView, Escape, Fractal, Parameter};
+use crate::mandelbrot::BAILOUT_RADIUS;
+
+// the variants of z -> z^2 + c that take absolute values or conjugates along the way
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fold {
+    BurningShip,   // (|re z| + i|im z|)^2 + c
+    Tricorn,       // conj(z)^2 + c, also known as the mandelbar set
+    Celtic,        // |re(z^2)| + i im(z^2) + c
+    Perpendicular, // re(z^2) - 2i|re z| im z + c
+}
+
+impl Fold {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fold::BurningShip => "Burning Ship",
+            Fold::Tricorn => "Tricorn",
+            Fold::Celtic => "Celtic",
+            Fold::Perpendicular => "Perpendicular",
+        }
+    }
+    fn step(self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let (x, y) = (z.re, z.im);
+        let (re, im) = match self {
+            Fold::BurningShip => (x * x - y * y, 2.0 * (x * y).abs()),
+            Fold::Tricorn => (x * x - y * y, -2.0 * x * y),
+            Fold::Celtic => ((x * x - y * y).abs(), 2.0 * x * y),
+            Fold::Perpendicular => (x * x - y * y, -2.0 * x.abs() * y),
+        };
+        Complex::new(re + c.re, im + c.im)
+    }
+    // the ship hangs below the real axis, the other sets are centred on it
+    fn default_view(self) -> DefaultView {
+        let (center, radius) = match self {
+            Fold::BurningShip => (Complex::new(-0.45, -0.5), 1.7),
+            Fold::Tricorn => (Complex::new(-0.3, 0.0), 1.6),
+            Fold::Celtic => (Complex::new(-0.5, 0.0), 1.6),
+            Fold::Perpendicular => (Complex::new(-0.5, 0.0), 1.5),
+        };
+        DefaultView { center, radius }
+    }
+}
+
+// the parameter plane of a fold, starting from z = 0
+pub struct Variant {
+    pub fold: Fold,
+}
+
+// the dynamic plane of a fold for a fixed c, starting from z at the point itself
+pub struct VariantJulia {
+    pub fold: Fold,
+    pub c: Complex<f64>,
+}
+
+impl Fractal for Variant {
+    fn name(&self) -> String {
+        String::from(self.fold.name())
+    }
+    fn julia(&self, c: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        Some(Arc::new(VariantJulia { fold: self.fold, c }))
+    }
+    fn default_view(&self) -> DefaultView {
+        self.fold.default_view()
+    }
+    fn compute(&self, c: Complex<f64>, n_iter: u16) -> Escape {
+        iterate(self.fold, Complex::new(0.0, 0.0), c, n_iter)
+    }
+}
+
+impl Fractal for VariantJulia {
+    fn name(&self) -> String {
+        format!("{} Julia", self.fold.name())
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter { name: "c", value: self.c }]
+    }
+    fn with_parameter(&self, name: &str, value: Complex<f64>) -> Option<Arc<dyn Fractal>> {
+        match name {
+            "c" => Some(Arc::new(VariantJulia { fold: self.fold, c: value })),
+            _ => None,
+        }
+    }
+    // the folds do not change |z^2|, so the julia sets stay within the circle of radius 2 like z^2 + c
+    fn default_view(&self) -> DefaultView {
+        DefaultView {
+            center: Complex::new(0.0, 0.0),
+            radius: 1.6,
+        }
+    }
+    fn compute(&self, z: Complex<f64>, n_iter: u16) -> Escape {
+        iterate(self.fold, z, self.c, n_iter)
+    }
+}
+
+fn iterate(fold: Fold, z: Complex<f64>, c: Complex<f64>, n_iter: u16) -> Escape {
+    let mut z = z;
+    let mut z_norm = z.norm_sqr();
+    for i in 0..n_iter {
+        z = fold.step(z, c);
+        z_norm = z.norm_sqr();
+        if z_norm > BAILOUT_RADIUS * BAILOUT_RADIUS {
+            return Escape::escaped(i, z_norm, 2.0);
+        }
+    }
+    Escape::bounded(n_iter, z_norm)
+}
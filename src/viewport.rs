@@ -0,0 +1,97 @@
+use num::complex::Complex;
+
+use crate::fixed::{FixedComplex, MAX_FRACTION_LIMBS};
+
+// f64 coordinates are good for about 52 bits, a few of which are needed to tell neighbouring samples apart
+const DOUBLE_RESOLUTION: f64 = 1e-12;
+// bits kept beyond the size of a sample, so that the error in the center never shows
+const GUARD_BITS: f64 = 64.0;
+
+// how the coordinates of the samples are computed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Precision {
+    Double,
+    Fixed { fraction_limbs: usize },
+}
+
+impl Precision {
+    pub fn name(self) -> String {
+        match self {
+            Precision::Double => String::from("f64"),
+            Precision::Fixed { fraction_limbs } => format!("{} bit fixed point", 32 * fraction_limbs),
+        }
+    }
+}
+
+// the part of the complex plane that is shown on a grid of samples.
+// sample (x, y) of a w x h grid sits (x - w/2, y - h/2) samples away from the center,
+// which is turned by rotation and scaled by scale horizontally and scale * aspect vertically
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub center: Complex<f64>, // center_fixed rounded to f64
+    pub center_fixed: FixedComplex, // the exact center, it only ever moves by offsets of a few samples
+    pub scale: f64,    // distance in the complex plane between two horizontally adjacent samples
+    pub aspect: f64,   // height of a sample divided by its width
+    pub rotation: f64, // counterclockwise, in radians
+}
+
+impl Viewport {
+    pub fn new(center: Complex<f64>, scale: f64, aspect: f64) -> Viewport {
+        Viewport {
+            center,
+            center_fixed: FixedComplex::from_f64(center, fraction_limbs_for(scale)),
+            scale,
+            aspect,
+            rotation: 0.0,
+        }
+    }
+    // double precision while it can still resolve a sample at this distance from the origin
+    pub fn precision(&self) -> Precision {
+        if self.scale > DOUBLE_RESOLUTION * f64::max(1.0, self.center.norm()) {
+            return Precision::Double;
+        }
+        Precision::Fixed {
+            fraction_limbs: fraction_limbs_for(self.scale),
+        }
+    }
+    // the complex offset of a step of (dx, dy) samples, y grows downwards on screen
+    pub fn offset(&self, dx: f64, dy: f64) -> Complex<f64> {
+        Complex::new(dx * self.scale, -dy * self.scale * self.aspect) * Complex::from_polar(1.0, self.rotation)
+    }
+    // the complex coordinate of a sample, which does not have to be inside the grid
+    pub fn point(&self, x: f64, y: f64, size: (u16, u16)) -> Complex<f64> {
+        self.center + self.offset(x - size.0 as f64 / 2.0, y - size.1 as f64 / 2.0)
+    }
+    // the same point in fixed point, the offset is small enough for f64 to hold it exactly enough
+    pub fn point_fixed(&self, x: f64, y: f64, size: (u16, u16), fraction_limbs: usize) -> FixedComplex {
+        let offset = self.offset(x - size.0 as f64 / 2.0, y - size.1 as f64 / 2.0);
+        let center = self.center_fixed.with_fraction_limbs(fraction_limbs);
+        center.add(&FixedComplex::from_f64(offset, fraction_limbs))
+    }
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.move_center(self.offset(dx, dy));
+    }
+    // if zoom_amount > 1 => zoom in, else => zoom out.
+    // the point at the anchor sample keeps its place on the grid
+    pub fn zoom(&mut self, zoom_amount: f64, anchor: (f64, f64), size: (u16, u16)) {
+        let (dx, dy) = (anchor.0 - size.0 as f64 / 2.0, anchor.1 - size.1 as f64 / 2.0);
+        let before = self.offset(dx, dy);
+        self.scale /= zoom_amount;
+        self.move_center(before - self.offset(dx, dy));
+    }
+    // the center keeps enough bits for the current scale, and never loses the ones it already has
+    fn move_center(&mut self, delta: Complex<f64>) {
+        let fraction_limbs = std::cmp::max(self.center_fixed.fraction_limbs(), fraction_limbs_for(self.scale));
+        self.center_fixed = self
+            .center_fixed
+            .with_fraction_limbs(fraction_limbs)
+            .add(&FixedComplex::from_f64(delta, fraction_limbs));
+        self.center = self.center_fixed.to_f64();
+    }
+}
+
+// 32 bit limbs that resolve a sample of this size with GUARD_BITS to spare
+pub fn fraction_limbs_for(scale: f64) -> usize {
+    let bits = -scale.log2().min(0.0) + GUARD_BITS;
+    ((bits / 32.0).ceil() as usize).clamp(2, MAX_FRACTION_LIMBS)
+}